@@ -0,0 +1,244 @@
+use std::fmt;
+
+use argon2::{
+    password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use log::warn;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+
+/// Access rules stored with a device in the registry
+///
+/// The PIN hash is left out of the [`Debug`] output, devices are logged with it.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Access {
+    /// When the device is considered, always if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    /// Whether approvers are asked when the device is detected
+    #[serde(default)]
+    pub trust: Trust,
+    /// PIN that has to be entered after detection, hashed with [`hash_pin`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+}
+
+impl fmt::Debug for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Access")
+            .field("schedule", &self.schedule)
+            .field("trust", &self.trust)
+            .field("pin", &self.pin.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Access {
+    /// Whether `pin` matches the stored PIN, `true` if no PIN is required
+    pub fn verify_pin(&self, pin: &str) -> bool {
+        let hash = match self.pin.as_ref() {
+            Some(hash) => hash,
+            None => return true,
+        };
+
+        match PasswordHash::new(hash) {
+            Ok(hash) => Argon2::default()
+                .verify_password(pin.as_bytes(), &hash)
+                .is_ok(),
+            Err(e) => {
+                warn!("Invalid PIN hash in registry: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Hashes a PIN for the registry
+pub fn hash_pin(pin: &str) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Devices carrying [`Access`] rules
+pub trait Restricted {
+    fn access(&self) -> &Access;
+}
+
+/// How far a device is trusted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trust {
+    /// Access is granted without asking an approver
+    Allow,
+    /// Approvers decide
+    #[default]
+    Approve,
+    /// Access is denied without asking an approver
+    Deny,
+}
+
+/// What happens to a device detected outside its schedule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outside {
+    /// The detection is dropped silently
+    #[default]
+    Ignore,
+    /// Access is denied without asking an approver
+    Deny,
+}
+
+/// Access windows of a device, the device is considered within any of them
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Schedule {
+    pub windows: Vec<Window>,
+    #[serde(default)]
+    pub outside: Outside,
+}
+
+impl Schedule {
+    /// Whether any window contains the given local time
+    pub fn allows(&self, at: NaiveDateTime) -> bool {
+        self.windows.iter().any(|window| window.contains(at))
+    }
+}
+
+/// A recurring time range, unset fields do not restrict the window
+///
+/// Times are given as `"HH:MM:SS"`, dates as `"YYYY-MM-DD"`, weekdays as `"Tue"` or `"Tuesday"`.
+/// A time range spanning midnight belongs to the day it starts on:
+/// a Friday window from 22:00 until 06:00 includes early Saturday, but not early Friday.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Window {
+    /// Days of the week the window applies to, every day if empty
+    pub weekdays: Vec<Weekday>,
+    /// Start of the daily time range
+    pub from: Option<NaiveTime>,
+    /// End of the daily time range, exclusive
+    pub until: Option<NaiveTime>,
+    /// First day the window applies to
+    pub start: Option<NaiveDate>,
+    /// Last day the window applies to, inclusive
+    pub end: Option<NaiveDate>,
+}
+
+impl Window {
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let (date, time) = (at.date(), at.time());
+
+        match (self.from, self.until) {
+            // ranges like 22:00-06:00 span midnight, the early hours belong to the previous day
+            (Some(from), Some(until)) if from > until => {
+                (time >= from && self.applies(date))
+                    || (time < until && matches!(date.pred_opt(), Some(day) if self.applies(day)))
+            }
+            (from, until) => {
+                self.applies(date)
+                    && !matches!(from, Some(from) if time < from)
+                    && !matches!(until, Some(until) if time >= until)
+            }
+        }
+    }
+
+    /// Whether the window applies to a day, by weekday and date range
+    fn applies(&self, date: NaiveDate) -> bool {
+        (self.weekdays.is_empty() || self.weekdays.contains(&date.weekday()))
+            && !matches!(self.start, Some(start) if date < start)
+            && !matches!(self.end, Some(end) if date > end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+    use super::{hash_pin, Access, Window};
+
+    /// 2021-06-04 is a Friday
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2021, 6, day).and_hms(hour, 0, 0)
+    }
+
+    fn night() -> Window {
+        Window {
+            from: Some(NaiveTime::from_hms(22, 0, 0)),
+            until: Some(NaiveTime::from_hms(6, 0, 0)),
+            ..Window::default()
+        }
+    }
+
+    #[test]
+    fn window_checks_weekday() {
+        let window = Window {
+            weekdays: vec![Weekday::Fri],
+            from: Some(NaiveTime::from_hms(8, 0, 0)),
+            until: Some(NaiveTime::from_hms(18, 0, 0)),
+            ..Window::default()
+        };
+
+        assert!(window.contains(at(4, 8)));
+        assert!(!window.contains(at(4, 18)));
+        assert!(!window.contains(at(5, 12)));
+    }
+
+    #[test]
+    fn window_checks_date_range() {
+        let window = Window {
+            start: Some(NaiveDate::from_ymd(2021, 6, 4)),
+            end: Some(NaiveDate::from_ymd(2021, 6, 5)),
+            ..Window::default()
+        };
+
+        assert!(!window.contains(at(3, 23)));
+        assert!(window.contains(at(4, 0)));
+        assert!(window.contains(at(5, 23)));
+        assert!(!window.contains(at(6, 0)));
+    }
+
+    #[test]
+    fn window_spanning_midnight_belongs_to_its_first_day() {
+        let window = Window {
+            weekdays: vec![Weekday::Fri],
+            ..night()
+        };
+
+        assert!(window.contains(at(4, 23)));
+        assert!(window.contains(at(5, 5)));
+        assert!(!window.contains(at(4, 5)));
+        assert!(!window.contains(at(5, 23)));
+        assert!(!window.contains(at(4, 12)));
+    }
+
+    #[test]
+    fn window_spanning_midnight_ends_after_its_last_day() {
+        let window = Window {
+            start: Some(NaiveDate::from_ymd(2021, 6, 4)),
+            end: Some(NaiveDate::from_ymd(2021, 6, 4)),
+            ..night()
+        };
+
+        assert!(!window.contains(at(4, 5)));
+        assert!(window.contains(at(4, 22)));
+        assert!(window.contains(at(5, 5)));
+        assert!(!window.contains(at(5, 22)));
+    }
+
+    #[test]
+    fn debug_redacts_pin() {
+        let hash = hash_pin("1234").unwrap();
+        let access = Access {
+            pin: Some(hash.clone()),
+            ..Access::default()
+        };
+
+        let debug = format!("{:?}", access);
+        assert!(!debug.contains(&hash));
+        assert!(debug.contains("<redacted>"));
+        assert!(access.verify_pin("1234"));
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    interfaces::services::AuthenticateResult,
+    manager::{Actuation, Attempt, ErrorReport, Event, Observer},
+};
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Error writing audit log: {0}")]
+    IO(#[from] io::Error),
+
+    #[error("Error serializing audit entry: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error("Error reading audit log: {0}")]
+    IO(#[from] io::Error),
+
+    #[error("Entry {line} is not valid: {error}")]
+    Parse {
+        line: usize,
+        error: serde_json::Error,
+    },
+
+    #[error("Entry {line} is numbered {found}: entries were removed or reordered")]
+    Sequence { line: usize, found: u64 },
+
+    #[error("Entry {line} does not follow the previous entry: entries were modified, removed or reordered")]
+    Chain { line: usize },
+
+    #[error("The log ends in {found} instead of {expected}: entries were removed or modified")]
+    Head { expected: String, found: String },
+}
+
+/// Hash of the entry preceding the first one
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Hashes a line of the log without its line break
+fn hash(line: &[u8]) -> String {
+    hex::encode(Sha256::digest(line))
+}
+
+/// A single line of the audit log
+#[derive(Debug, Serialize)]
+pub struct Entry<'a> {
+    pub timestamp: DateTime<Utc>,
+    pub device: String,
+    pub detector: &'a str,
+    pub approver: Option<&'a str>,
+    pub decision: &'a AuthenticateResult,
+    pub latency_ms: u128,
+    pub actuator: &'a Actuation,
+}
+
+impl<'a> Entry<'a> {
+    pub fn new<Device: Display>(attempt: &'a Attempt<'_, Device>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            device: attempt.device.to_string(),
+            detector: &attempt.detector,
+            approver: attempt.approver.as_deref(),
+            decision: &attempt.decision,
+            latency_ms: attempt.latency.as_millis(),
+            actuator: &attempt.actuation,
+        }
+    }
+}
+
+/// A line of the audit log recording a failed service
+#[derive(Debug, Serialize)]
+pub struct ErrorEntry<'a> {
+    pub timestamp: DateTime<Utc>,
+    pub error: &'a ErrorReport,
+}
+
+impl<'a> ErrorEntry<'a> {
+    pub fn new(error: &'a ErrorReport) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            error,
+        }
+    }
+}
+
+/// Links an entry to its predecessor
+#[derive(Serialize)]
+struct Chained<'a, E> {
+    /// Position of the entry in the log, starting at 0
+    seq: u64,
+    /// Hash of the previous line, [`GENESIS`] for the first one
+    prev: &'a str,
+    #[serde(flatten)]
+    entry: &'a E,
+}
+
+/// The chain fields of a line read back for verification
+#[derive(Deserialize)]
+struct Link {
+    seq: u64,
+    prev: String,
+}
+
+struct Tail {
+    file: File,
+    seq: u64,
+    prev: String,
+}
+
+/// Append-only, tamper-evident JSON Lines log of every attempt and service error
+///
+/// Every entry carries its line number and the hash of the previous line,
+/// so modified, removed or reordered entries are found by [`verify`].
+/// Each entry is synced to disk before the manager continues.
+/// Register it with [`Manager::observe`](crate::manager::Manager::observe).
+pub struct AuditLog {
+    tail: Mutex<Tail>,
+}
+
+impl AuditLog {
+    /// Opens or creates the log at `path`, new entries continue the existing chain
+    ///
+    /// A partial last line left by an interrupted write is removed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AuditError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+
+        // a crash during a write may have left a partial line, the entry was never recorded
+        let complete = content
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |end| end + 1);
+        if complete < content.len() {
+            warn!("Dropping partial last entry of the audit log");
+            file.set_len(complete as u64)?;
+            file.sync_data()?;
+            content.truncate(complete);
+        }
+
+        let (seq, prev) = match content.strip_suffix(b"\n") {
+            None => (0, GENESIS.to_string()),
+            Some(content) => {
+                let lines = content.split(|byte| *byte == b'\n').collect::<Vec<_>>();
+                (lines.len() as u64, hash(lines[lines.len() - 1]))
+            }
+        };
+
+        Ok(Self {
+            tail: Mutex::new(Tail { file, seq, prev }),
+        })
+    }
+
+    pub fn append(&self, entry: &Entry<'_>) -> Result<(), AuditError> {
+        self.chain(entry)
+    }
+
+    pub fn append_error(&self, entry: &ErrorEntry<'_>) -> Result<(), AuditError> {
+        self.chain(entry)
+    }
+
+    fn chain(&self, entry: &impl Serialize) -> Result<(), AuditError> {
+        let mut tail = self.tail.lock().unwrap();
+
+        let mut line = serde_json::to_vec(&Chained {
+            seq: tail.seq,
+            prev: &tail.prev,
+            entry,
+        })?;
+        let next = hash(&line);
+        line.push(b'\n');
+
+        tail.file.write_all(&line)?;
+        tail.file.sync_data()?;
+        tail.seq += 1;
+        tail.prev = next;
+        Ok(())
+    }
+}
+
+/// Checks the chain of an audit log, returns the number of entries and the hash of the last one
+///
+/// Entries removed from the end of the log can only be detected against a previously
+/// recorded `head`, the hash returned by an earlier verification or [`AuditLog`].
+pub fn verify(log: impl BufRead, head: Option<&str>) -> Result<(u64, String), VerifyError> {
+    let mut prev = GENESIS.to_string();
+    let mut entries = 0;
+
+    for (line, content) in log.split(b'\n').enumerate() {
+        let content = content?;
+        let link: Link =
+            serde_json::from_slice(&content).map_err(|error| VerifyError::Parse { line, error })?;
+
+        if link.seq != line as u64 {
+            return Err(VerifyError::Sequence {
+                line,
+                found: link.seq,
+            });
+        }
+        if link.prev != prev {
+            return Err(VerifyError::Chain { line });
+        }
+
+        prev = hash(&content);
+        entries += 1;
+    }
+
+    match head {
+        Some(head) if head != prev => Err(VerifyError::Head {
+            expected: head.to_string(),
+            found: prev,
+        }),
+        _ => Ok((entries, prev)),
+    }
+}
+
+impl<Device: Display> Observer<Device> for AuditLog {
+    fn notify(&self, event: &Event<'_, Device>) {
+        match event {
+            Event::Attempt(attempt) => {
+                if let Err(e) = self.append(&Entry::new(attempt)) {
+                    error!("Could not record attempt: {}", e);
+                }
+            }
+            Event::ServiceError(report) => {
+                if let Err(e) = self.append_error(&ErrorEntry::new(report)) {
+                    error!("Could not record service error: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::{hash, verify, AuditLog, Chained, ErrorEntry, VerifyError, GENESIS};
+    use crate::manager::{DoorState, ErrorReport, Service};
+
+    /// A log of `entries` chained lines
+    fn log(entries: u64) -> Vec<Vec<u8>> {
+        let mut prev = GENESIS.to_string();
+        (0..entries)
+            .map(|seq| {
+                let line = serde_json::to_vec(&Chained {
+                    seq,
+                    prev: &prev,
+                    entry: &json!({ "n": seq }),
+                })
+                .unwrap();
+                prev = hash(&line);
+                line
+            })
+            .collect()
+    }
+
+    fn join(lines: &[Vec<u8>]) -> Vec<u8> {
+        lines
+            .iter()
+            .flat_map(|line| line.iter().copied().chain(Some(b'\n')))
+            .collect()
+    }
+
+    #[test]
+    fn intact_log_verifies() {
+        let (entries, head) = verify(&join(&log(3))[..], None).unwrap();
+        assert_eq!(entries, 3);
+        assert!(verify(&join(&log(3))[..], Some(&head)).is_ok());
+    }
+
+    #[test]
+    fn modified_line_breaks_chain() {
+        let mut lines = log(3);
+        lines[1] = String::from_utf8(lines[1].clone())
+            .unwrap()
+            .replace("\"n\":1", "\"n\":7")
+            .into_bytes();
+
+        assert!(matches!(
+            verify(&join(&lines)[..], None),
+            Err(VerifyError::Chain { line: 2 })
+        ));
+    }
+
+    #[test]
+    fn reordered_lines_break_sequence() {
+        let mut lines = log(3);
+        lines.swap(1, 2);
+
+        assert!(matches!(
+            verify(&join(&lines)[..], None),
+            Err(VerifyError::Sequence { line: 1, found: 2 })
+        ));
+    }
+
+    #[test]
+    fn removed_middle_line_breaks_sequence() {
+        let mut lines = log(3);
+        lines.remove(1);
+
+        assert!(matches!(
+            verify(&join(&lines)[..], None),
+            Err(VerifyError::Sequence { line: 1, found: 2 })
+        ));
+    }
+
+    #[test]
+    fn truncated_tail_only_found_with_head() {
+        let lines = log(3);
+        let (_, head) = verify(&join(&lines)[..], None).unwrap();
+
+        assert!(verify(&join(&lines[..2])[..], None).is_ok());
+        assert!(matches!(
+            verify(&join(&lines[..2])[..], Some(&head)),
+            Err(VerifyError::Head { .. })
+        ));
+    }
+
+    #[test]
+    fn open_drops_partial_last_line() {
+        let path = std::env::temp_dir().join(format!("doorman-audit-{}.log", std::process::id()));
+        let report = ErrorReport {
+            service: Service::Locker,
+            error: "jammed".to_string(),
+            phase: DoorState::Open,
+            device: None,
+            retries: 0,
+            fatal: false,
+            recoverable: true,
+        };
+
+        let mut content = join(&log(2));
+        content.extend_from_slice(b"{\"seq\":2,\"pr");
+        fs::write(&path, content).unwrap();
+
+        AuditLog::open(&path)
+            .unwrap()
+            .append_error(&ErrorEntry::new(&report))
+            .unwrap();
+        let result = verify(&fs::read(&path).unwrap()[..], None);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap().0, 3);
+    }
+}
@@ -1,5 +1,7 @@
 use clap::Clap;
 #[derive(Clap, Debug, Clone)]
 pub struct Args {
-
+    /// A detected device is ignored until it was out of range for this long (in sec)
+    #[clap(long, env = "BLUETOOTH_ABSENCE", default_value = "300")]
+    pub absence: u64,
 }
@@ -1,5 +1,7 @@
 
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use futures::lock::Mutex;
 use log::{debug, info, warn};
@@ -11,6 +13,8 @@ use bluez::interface::event::Event;
 use bluez::Error as BluezError;
 
 use doorman::interfaces::services::{self, Registry, ServiceError};
+#[cfg(feature = "metrics")]
+use doorman::metrics::Metrics;
 use thiserror::Error;
 
 use super::device::BluetoothDevice;
@@ -24,17 +28,22 @@ pub enum DetectorError {
     NoDevice,
 }
 
-impl ServiceError for DetectorError {}
+impl ServiceError for DetectorError {
+    fn is_fatal(&self) -> bool {
+        matches!(self, DetectorError::NoDevice)
+    }
+}
 
-pub struct BluetoothDetector<'a, Reg: Registry + Send + Sync> {
-    registry: &'a Reg,
-    client: Mutex<BlueZClient<'a>>,
+/// A client with the controller it discovers devices with
+struct Connection {
+    client: BlueZClient<'static>,
     controller: Controller,
 }
 
-impl<'a, Reg: Registry + Send + Sync> BluetoothDetector<'a, Reg> {
-    pub async fn new(registry: &'a Reg) -> Result<BluetoothDetector<'a, Reg>, DetectorError> {
-        let mut client = BlueZClient::new().unwrap();
+impl Connection {
+    /// Connects to the first controller that can be powered on and powers it on
+    async fn open() -> Result<Self, DetectorError> {
+        let mut client = BlueZClient::new()?;
         let controllers = client.get_controller_list().await?;
 
         // find the first controller we can power on
@@ -56,29 +65,51 @@ impl<'a, Reg: Registry + Send + Sync> BluetoothDetector<'a, Reg> {
             client.set_powered(controller, true).await?;
         }
 
+        Ok(Self { client, controller })
+    }
+}
+
+pub struct BluetoothDetector<Reg: Registry + Send + Sync> {
+    registry: Arc<Reg>,
+    connection: Mutex<Connection>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl<Reg: Registry + Send + Sync> BluetoothDetector<Reg> {
+    pub async fn new(registry: Arc<Reg>) -> Result<BluetoothDetector<Reg>, DetectorError> {
         Ok(Self {
             registry,
-            client: Mutex::new(client),
-            controller,
+            connection: Mutex::new(Connection::open().await?),
+            #[cfg(feature = "metrics")]
+            metrics: None,
         })
     }
+
+    /// Count processed bluetooth events
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(self, metrics: Option<Metrics>) -> Self {
+        Self { metrics, ..self }
+    }
 }
 
 #[async_trait]
-impl<'a, Reg: Registry<Ident = String, Device = BluetoothDevice> + Send + Sync> services::Detector
-    for BluetoothDetector<'a, Reg>
+impl<Reg: Registry<Ident = String, Device = BluetoothDevice> + Send + Sync> services::Detector
+    for BluetoothDetector<Reg>
 {
     type Device = BluetoothDevice;
     type DetectorError = DetectorError;
 
-    async fn wait_for_device(&self) -> Result<&Self::Device, DetectorError> {
-        let mut client = self.client.lock().await;
+    async fn wait_for_device(&self) -> Result<Self::Device, DetectorError> {
+        let mut connection = self.connection.lock().await;
+        let Connection { client, controller } = &mut *connection;
+        let controller = *controller;
 
         // scan for some devices
         // to do this we'll need to listen for the Device Found event
         client
             .start_discovery(
-                self.controller,
+                controller,
                 AddressTypeFlag::BREDR | AddressTypeFlag::LEPublic | AddressTypeFlag::LERandom,
             )
             .await?;
@@ -88,6 +119,10 @@ impl<'a, Reg: Registry<Ident = String, Device = BluetoothDevice> + Send + Sync>
             // process() blocks until there is a response to be had
             let response = client.process().await?;
             debug!("Processing bluetooth event {:?}", response.event);
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &self.metrics {
+                metrics.bluetooth_event();
+            }
             match response.event {
                 Event::DeviceFound {
                     address,
@@ -98,7 +133,7 @@ impl<'a, Reg: Registry<Ident = String, Device = BluetoothDevice> + Send + Sync>
                 } => {
                     if let Some(device) = self.registry.check(&address.to_string()) {
                         info!("Registered device {} found with RSSI {}", device, rssi);
-                        return Ok(&device);
+                        return Ok(device.clone());
                     };
                 }
                 Event::Discovering {
@@ -110,7 +145,7 @@ impl<'a, Reg: Registry<Ident = String, Device = BluetoothDevice> + Send + Sync>
                     if !discovering {
                         client
                             .start_discovery(
-                                self.controller,
+                                controller,
                                 AddressTypeFlag::BREDR
                                     | AddressTypeFlag::LEPublic
                                     | AddressTypeFlag::LERandom,
@@ -124,4 +159,15 @@ impl<'a, Reg: Registry<Ident = String, Device = BluetoothDevice> + Send + Sync>
             sleep(Duration::from_millis(50)).await;
         }
     }
+
+    fn source(&self, _device: &Self::Device) -> String {
+        "bluetooth".to_string()
+    }
+
+    /// Reconnects to the controller, e.g. after it was unplugged or bluetoothd restarted
+    async fn restart(&self) -> Result<(), DetectorError> {
+        info!("Reconnecting to bluetooth controller");
+        *self.connection.lock().await = Connection::open().await?;
+        Ok(())
+    }
 }
@@ -1,14 +1,23 @@
 use derive_more::{Constructor, Display};
+use doorman::access::{Access, Restricted};
 use serde::{Deserialize, Serialize};
 
 pub type Address = String;
 
-#[derive(Debug, Clone, Constructor, Display, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Constructor, Display, Serialize, Deserialize)]
 #[display(fmt = "{}/{} ({})", name, address, rssi_reference)]
 pub struct BluetoothDevice {
     name: String,
     address: Address,
     rssi_reference: u64,
+    #[serde(flatten)]
+    access: Access,
+}
+
+impl Restricted for BluetoothDevice {
+    fn access(&self) -> &Access {
+        &self.access
+    }
 }
 
 impl Into<Address> for BluetoothDevice {
@@ -0,0 +1,467 @@
+use std::{sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use log::{debug, info, warn};
+use thiserror::Error;
+
+use crate::interfaces::services::{Authenticate, AuthenticateResult, Notice, Withdrawal};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ApproversError {
+    #[error("No authenticators to ask")]
+    NoAuthenticators,
+
+    #[error("Quorum of {required} cannot be reached by {total} authenticators")]
+    UnreachableQuorum { required: usize, total: usize },
+}
+
+/// How the answers of several approvers are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// The first approver to allow or deny decides
+    Any,
+    /// Every approver has to allow, a single deny decides
+    All,
+    /// At least the given number of approvers have to allow
+    Quorum(usize),
+}
+
+impl Policy {
+    /// Decides once the outcome can no longer change
+    fn decide(
+        &self,
+        total: usize,
+        allowed: usize,
+        denied: usize,
+        timed_out: usize,
+    ) -> Option<AuthenticateResult> {
+        let required = match *self {
+            Policy::Any if allowed > 0 => return Some(AuthenticateResult::Allow),
+            Policy::Any if denied > 0 => return Some(AuthenticateResult::Deny),
+            Policy::Any => 1,
+            Policy::All => total,
+            Policy::Quorum(required) => required,
+        };
+
+        let pending = total - allowed - denied - timed_out;
+        if allowed >= required {
+            Some(AuthenticateResult::Allow)
+        } else if allowed + pending < required {
+            if denied > 0 {
+                Some(AuthenticateResult::Deny)
+            } else {
+                Some(AuthenticateResult::Timeout)
+            }
+        } else {
+            None
+        }
+    }
+}
+
+/// Resolves once any of the authenticators acknowledged an alert
+async fn any_acknowledged<'a, A: Authenticate + Sync + 'a>(
+    authenticators: impl Iterator<Item = &'a A>,
+) -> Result<(), A::AuthenticateError> {
+    let mut acknowledged = authenticators
+        .map(|auth| auth.acknowledged())
+        .collect::<FuturesUnordered<_>>();
+    match acknowledged.next().await {
+        Some(result) => result,
+        None => futures::future::pending().await,
+    }
+}
+
+/// The lesser of two grants, a one-time grant is less than any timed one
+fn weakest(grant: Option<AuthenticateResult>, answer: AuthenticateResult) -> AuthenticateResult {
+    match (grant, answer) {
+        (Some(AuthenticateResult::AllowFor(a)), AuthenticateResult::AllowFor(b)) => {
+            AuthenticateResult::AllowFor(a.min(b))
+        }
+        (None, answer) => answer,
+        _ => AuthenticateResult::Allow,
+    }
+}
+
+/// Asks several authenticators in parallel and decides by [`Policy`]
+///
+/// If several approvers allow, the door is opened by the weakest of their grants,
+/// a denial is passed on as given by the approver who answered last.
+/// Requests still pending once the outcome is decided are withdrawn.
+/// An error of any authenticator aborts the whole request.
+pub struct Approvers<A> {
+    authenticators: Vec<A>,
+    policy: Policy,
+    /// The authenticator whose answer decided the most recent request
+    decided_by: Mutex<Option<usize>>,
+}
+
+impl<A> Approvers<A> {
+    /// Fails without authenticators or if a quorum can never be reached by them
+    pub fn new(authenticators: Vec<A>, policy: Policy) -> Result<Self, ApproversError> {
+        if authenticators.is_empty() {
+            return Err(ApproversError::NoAuthenticators);
+        }
+        if let Policy::Quorum(required) = policy {
+            if required == 0 || required > authenticators.len() {
+                return Err(ApproversError::UnreachableQuorum {
+                    required,
+                    total: authenticators.len(),
+                });
+            }
+        }
+
+        Ok(Self {
+            authenticators,
+            policy,
+            decided_by: Mutex::new(None),
+        })
+    }
+
+    pub fn any(authenticators: Vec<A>) -> Result<Self, ApproversError> {
+        Self::new(authenticators, Policy::Any)
+    }
+
+    pub fn all(authenticators: Vec<A>) -> Result<Self, ApproversError> {
+        Self::new(authenticators, Policy::All)
+    }
+
+    pub fn quorum(authenticators: Vec<A>, required: usize) -> Result<Self, ApproversError> {
+        Self::new(authenticators, Policy::Quorum(required))
+    }
+}
+
+#[async_trait]
+impl<A> Authenticate for Approvers<A>
+where
+    A: Authenticate + Send + Sync,
+{
+    type Device = A::Device;
+    type AuthenticateError = A::AuthenticateError;
+
+    async fn authenticate(
+        &self,
+        device: &Self::Device,
+        timeout: Option<Duration>,
+    ) -> Result<AuthenticateResult, Self::AuthenticateError> {
+        let total = self.authenticators.len();
+        let mut answered = vec![false; total];
+        let (mut allowed, mut denied, mut timed_out) = (0, 0, 0);
+        let mut last_answer = None;
+        // the answers decide how access is granted or denied
+        let (mut grant, mut denial) = (None, None);
+
+        let mut requests = self
+            .authenticators
+            .iter()
+            .enumerate()
+            .map(|(index, auth)| {
+                auth.authenticate(device, timeout)
+                    .map(move |result| (index, result))
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let outcome = loop {
+            if let Some(decision) = self.policy.decide(total, allowed, denied, timed_out) {
+                break Ok(match decision {
+                    AuthenticateResult::Allow => grant.unwrap_or(decision),
+                    AuthenticateResult::Deny => denial.unwrap_or(decision),
+                    _ => decision,
+                });
+            }
+
+            let (index, result) = requests
+                .next()
+                .await
+                .expect("outcome is decided once all requests are answered");
+            answered[index] = true;
+
+            match result {
+                Ok(answer) if answer.is_allowed() => {
+                    allowed += 1;
+                    last_answer = Some(index);
+                    grant = Some(weakest(grant, answer));
+                }
+                Ok(answer) if answer.is_denied() => {
+                    denied += 1;
+                    last_answer = Some(index);
+                    denial = Some(answer);
+                }
+                Ok(_) => timed_out += 1,
+                Err(e) => break Err(e),
+            }
+            debug!(
+                "Approvals: {} allowed, {} denied, {} timed out of {}",
+                allowed, denied, timed_out, total
+            );
+        };
+
+        // dropping the remaining requests cancels them, their prompts are cleaned up below
+        drop(requests);
+        *self.decided_by.lock().unwrap() = match outcome {
+            Ok(AuthenticateResult::Timeout) | Err(_) => None,
+            Ok(_) => last_answer,
+        };
+
+        for (auth, _) in self
+            .authenticators
+            .iter()
+            .zip(answered)
+            .filter(|(_, answered)| !answered)
+        {
+            if let Err(e) = auth.withdraw(Withdrawal::Decided).await {
+                warn!("Could not withdraw authentication request: {}", e);
+            }
+        }
+
+        outcome
+    }
+
+    async fn withdraw(&self, reason: Withdrawal) -> Result<(), Self::AuthenticateError> {
+        for auth in self.authenticators.iter() {
+            auth.withdraw(reason).await?;
+        }
+        Ok(())
+    }
+
+    async fn inform(
+        &self,
+        notice: &Notice<'_, Self::Device>,
+    ) -> Result<(), Self::AuthenticateError> {
+        for auth in self.authenticators.iter() {
+            auth.inform(notice).await?;
+        }
+        Ok(())
+    }
+
+    async fn acknowledged(&self) -> Result<(), Self::AuthenticateError> {
+        any_acknowledged(self.authenticators.iter()).await
+    }
+
+    fn approver(&self) -> Option<String> {
+        let decided_by = *self.decided_by.lock().unwrap();
+        decided_by.and_then(|index| self.authenticators[index].approver())
+    }
+
+    async fn restart(&self) -> Result<(), Self::AuthenticateError> {
+        for auth in self.authenticators.iter() {
+            auth.restart().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Asks authenticators one after another until one of them answers
+///
+/// Each step waits for its own timeout (or the one passed to [`Authenticate::authenticate`]),
+/// if nobody answers the `fallback` result is returned.
+/// Notices reach all steps, except [`Notice::LeftUnlocked`] alerts which reach the first step
+/// until they are escalated.
+pub struct Escalation<A> {
+    steps: Vec<(A, Option<Duration>)>,
+    fallback: AuthenticateResult,
+    /// The step that answered the most recent request
+    decided_by: Mutex<Option<usize>>,
+}
+
+impl<A> Escalation<A> {
+    pub fn new(steps: Vec<(A, Option<Duration>)>, fallback: AuthenticateResult) -> Self {
+        Self {
+            steps,
+            fallback,
+            decided_by: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<A> Authenticate for Escalation<A>
+where
+    A: Authenticate + Send + Sync,
+{
+    type Device = A::Device;
+    type AuthenticateError = A::AuthenticateError;
+
+    async fn authenticate(
+        &self,
+        device: &Self::Device,
+        timeout: Option<Duration>,
+    ) -> Result<AuthenticateResult, Self::AuthenticateError> {
+        *self.decided_by.lock().unwrap() = None;
+        for (step, (auth, step_timeout)) in self.steps.iter().enumerate() {
+            match auth.authenticate(device, step_timeout.or(timeout)).await? {
+                AuthenticateResult::Timeout => {
+                    info!("Approver {} did not answer, escalating", step);
+                }
+                answer => {
+                    *self.decided_by.lock().unwrap() = Some(step);
+                    return Ok(answer);
+                }
+            }
+        }
+
+        info!("Nobody answered, falling back to {:?}", self.fallback);
+        Ok(self.fallback.clone())
+    }
+
+    async fn withdraw(&self, reason: Withdrawal) -> Result<(), Self::AuthenticateError> {
+        for (auth, _) in self.steps.iter() {
+            auth.withdraw(reason).await?;
+        }
+        Ok(())
+    }
+
+    async fn inform(
+        &self,
+        notice: &Notice<'_, Self::Device>,
+    ) -> Result<(), Self::AuthenticateError> {
+        // alerts reach further approvers only once escalated
+        let steps = match notice {
+            Notice::LeftUnlocked(_, false) => 1,
+            _ => self.steps.len(),
+        };
+        for (auth, _) in self.steps.iter().take(steps) {
+            auth.inform(notice).await?;
+        }
+        Ok(())
+    }
+
+    async fn acknowledged(&self) -> Result<(), Self::AuthenticateError> {
+        any_acknowledged(self.steps.iter().map(|(auth, _)| auth)).await
+    }
+
+    fn approver(&self) -> Option<String> {
+        let decided_by = *self.decided_by.lock().unwrap();
+        decided_by.and_then(|step| self.steps[step].0.approver())
+    }
+
+    async fn restart(&self) -> Result<(), Self::AuthenticateError> {
+        for (auth, _) in self.steps.iter() {
+            auth.restart().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Approvers, ApproversError, Policy};
+    use crate::{
+        interfaces::services::{Authenticate, AuthenticateResult, Withdrawal},
+        testing::{secs, AuthenticatorCall, MockAuthenticator, MockDevice},
+    };
+
+    fn withdrawn(auth: &MockAuthenticator<MockDevice>) -> bool {
+        auth.calls()
+            .iter()
+            .any(|(_, call)| *call == AuthenticatorCall::Withdraw(Withdrawal::Decided))
+    }
+
+    #[test]
+    fn policies_decide_once_the_outcome_is_certain() {
+        use AuthenticateResult::{Allow, Deny, Timeout};
+
+        // policy, allowed, denied, timed out of three approvers, decision
+        let cases = [
+            (Policy::Any, 0, 0, 0, None),
+            (Policy::Any, 1, 0, 0, Some(Allow)),
+            (Policy::Any, 0, 1, 0, Some(Deny)),
+            (Policy::Any, 0, 0, 2, None),
+            (Policy::Any, 0, 0, 3, Some(Timeout)),
+            (Policy::All, 2, 0, 0, None),
+            (Policy::All, 3, 0, 0, Some(Allow)),
+            (Policy::All, 0, 1, 0, Some(Deny)),
+            (Policy::All, 2, 1, 0, Some(Deny)),
+            (Policy::All, 2, 0, 1, Some(Timeout)),
+            (Policy::Quorum(2), 1, 0, 0, None),
+            (Policy::Quorum(2), 2, 0, 0, Some(Allow)),
+            (Policy::Quorum(2), 1, 1, 0, None),
+            (Policy::Quorum(2), 1, 2, 0, Some(Deny)),
+            (Policy::Quorum(2), 0, 1, 1, Some(Deny)),
+            (Policy::Quorum(2), 1, 0, 2, Some(Timeout)),
+        ];
+
+        for (policy, allowed, denied, timed_out, decision) in cases.iter().cloned() {
+            assert_eq!(
+                policy.decide(3, allowed, denied, timed_out),
+                decision,
+                "{:?} with {} allowed, {} denied, {} timed out",
+                policy,
+                allowed,
+                denied,
+                timed_out
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn any_withdraws_from_the_others_once_one_allowed() {
+        let first = MockAuthenticator::new();
+        let second = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Allow);
+        let approvers = Approvers::any(vec![first.clone(), second.clone()]).unwrap();
+
+        let decision = approvers
+            .authenticate(&MockDevice::new("phone"), None)
+            .await
+            .unwrap();
+
+        assert_eq!(decision, AuthenticateResult::Allow);
+        assert!(withdrawn(&first));
+        assert!(!withdrawn(&second));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn all_is_decided_by_a_single_deny() {
+        let first = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Deny);
+        let second = MockAuthenticator::new();
+        let approvers = Approvers::all(vec![first.clone(), second.clone()]).unwrap();
+
+        let decision = approvers
+            .authenticate(&MockDevice::new("phone"), None)
+            .await
+            .unwrap();
+
+        assert_eq!(decision, AuthenticateResult::Deny);
+        assert!(!withdrawn(&first));
+        assert!(withdrawn(&second));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quorum_withdraws_from_approvers_not_needed() {
+        let first = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Allow);
+        let second = MockAuthenticator::new().answer(secs(2), AuthenticateResult::Allow);
+        let third = MockAuthenticator::new();
+        let approvers =
+            Approvers::quorum(vec![first.clone(), second.clone(), third.clone()], 2).unwrap();
+
+        let decision = approvers
+            .authenticate(&MockDevice::new("phone"), None)
+            .await
+            .unwrap();
+
+        assert_eq!(decision, AuthenticateResult::Allow);
+        assert!(!withdrawn(&first));
+        assert!(!withdrawn(&second));
+        assert!(withdrawn(&third));
+    }
+
+    #[test]
+    fn approvers_need_authenticators() {
+        assert_eq!(
+            Approvers::<MockAuthenticator<MockDevice>>::new(vec![], Policy::All).err(),
+            Some(ApproversError::NoAuthenticators)
+        );
+    }
+
+    #[test]
+    fn quorum_must_be_reachable() {
+        let approvers = vec![MockAuthenticator::<MockDevice>::new(); 2];
+        assert_eq!(
+            Approvers::quorum(approvers, 3).err(),
+            Some(ApproversError::UnreachableQuorum {
+                required: 3,
+                total: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::future::{self, Either as Select};
+use log::{debug, warn};
+use thiserror::Error;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, error::TrySendError},
+        Mutex as AsyncMutex,
+    },
+    task::JoinHandle,
+    time::{sleep_until, Instant},
+};
+
+use super::Either;
+use crate::interfaces::services::{Detector, ServiceError};
+
+#[derive(Debug, Error)]
+#[error("All detectors failed: {0}; {1}")]
+pub struct RaceError<L: ServiceError, R: ServiceError>(pub L, pub R);
+
+impl<L: ServiceError, R: ServiceError> ServiceError for RaceError<L, R> {
+    fn is_fatal(&self) -> bool {
+        self.0.is_fatal() && self.1.is_fatal()
+    }
+}
+
+/// Runs two detectors concurrently, the first detection wins
+///
+/// If one of the detectors fails the other one keeps running,
+/// an error is only returned once both failed.
+/// More than two detectors can be combined by nesting races.
+pub struct Race<L, R> {
+    left: L,
+    right: R,
+}
+
+impl<L, R> Race<L, R> {
+    pub fn new(left: L, right: R) -> Self {
+        Self { left, right }
+    }
+}
+
+#[async_trait]
+impl<L, R> Detector for Race<L, R>
+where
+    L: Detector + Send + Sync,
+    R: Detector + Send + Sync,
+    L::Device: Send + Sync,
+    R::Device: Send + Sync,
+{
+    type Device = Either<L::Device, R::Device>;
+    type DetectorError = RaceError<L::DetectorError, R::DetectorError>;
+
+    async fn wait_for_device(&self) -> Result<Self::Device, Self::DetectorError> {
+        let left = self.left.wait_for_device();
+        let right = self.right.wait_for_device();
+
+        match future::select(left, right).await {
+            Select::Left((Ok(device), _)) => Ok(Either::Left(device)),
+            Select::Right((Ok(device), _)) => Ok(Either::Right(device)),
+            Select::Left((Err(left), right)) => {
+                warn!("Detector failed, waiting for remaining detector: {}", left);
+                right
+                    .await
+                    .map(Either::Right)
+                    .map_err(|right| RaceError(left, right))
+            }
+            Select::Right((Err(right), left)) => {
+                warn!("Detector failed, waiting for remaining detector: {}", right);
+                left.await
+                    .map(Either::Left)
+                    .map_err(|left| RaceError(left, right))
+            }
+        }
+    }
+
+    fn source(&self, device: &Self::Device) -> String {
+        match device {
+            Either::Left(device) => self.left.source(device),
+            Either::Right(device) => self.right.source(device),
+        }
+    }
+
+    async fn wait_for_departure(&self, device: &Self::Device)
+    where
+        Self::Device: Sync,
+    {
+        match device {
+            Either::Left(device) => self.left.wait_for_departure(device).await,
+            Either::Right(device) => self.right.wait_for_departure(device).await,
+        }
+    }
+
+    async fn restart(&self) -> Result<(), Self::DetectorError> {
+        let (left, right) = futures::join!(self.left.restart(), self.right.restart());
+        match (left, right) {
+            (Err(left), Err(right)) => Err(RaceError(left, right)),
+            _ => Ok(()),
+        }
+    }
+}
+
+type Detection<D> = Result<<D as Detector>::Device, <D as Detector>::DetectorError>;
+type Sightings<Device> = Arc<Mutex<HashMap<Device, Sighting>>>;
+
+/// A device came into or went out of range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresenceEvent<Device> {
+    Arrived(Device),
+    /// The device was not seen for the configured absence
+    Departed(Device),
+}
+
+#[derive(Debug)]
+struct Sighting {
+    last_seen: Instant,
+    /// Whether the device was taken by [`wait_for_device`](Detector::wait_for_device) since it arrived
+    reported: bool,
+}
+
+/// Tracks which devices are in range and reports each arrival once
+///
+/// The inner detector is polled continuously in the background, also while nobody waits for a
+/// detection, to keep track of when each device was last seen.
+/// A device that was not seen for `absence` has departed,
+/// [`wait_for_device`](Detector::wait_for_device) reports it again once it arrives again.
+/// Detections queued while nobody waited are dropped if the device departed in the meantime.
+/// Restarting stops polling until the inner detector restarted, polling stops at fatal errors.
+pub struct Presence<D: Detector> {
+    inner: Arc<D>,
+    seen: Sightings<D::Device>,
+    events: broadcast::Sender<PresenceEvent<D::Device>>,
+    sender: mpsc::Sender<Detection<D>>,
+    detections: AsyncMutex<mpsc::Receiver<Detection<D>>>,
+    absence: Duration,
+    watch: Mutex<JoinHandle<()>>,
+    sweep: JoinHandle<()>,
+}
+
+impl<D> Presence<D>
+where
+    D: Detector + Send + Sync + 'static,
+    D::Device: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    pub fn new(inner: D, absence: Duration) -> Self {
+        let inner = Arc::new(inner);
+        let seen = Arc::new(Mutex::new(HashMap::new()));
+        let (events, _) = broadcast::channel(16);
+        // devices seen while nobody waits are retried at their next sighting
+        let (sender, detections) = mpsc::channel(1);
+
+        let watch = tokio::spawn(Self::watch(
+            inner.clone(),
+            seen.clone(),
+            events.clone(),
+            sender.clone(),
+            absence,
+        ));
+        let sweep = tokio::spawn(Self::sweep(seen.clone(), events.clone(), absence));
+
+        Self {
+            inner,
+            seen,
+            events,
+            sender,
+            detections: AsyncMutex::new(detections),
+            absence,
+            watch: Mutex::new(watch),
+            sweep,
+        }
+    }
+
+    /// Records sightings of the inner detector and hands out arrivals
+    async fn watch(
+        inner: Arc<D>,
+        seen: Sightings<D::Device>,
+        events: broadcast::Sender<PresenceEvent<D::Device>>,
+        sender: mpsc::Sender<Detection<D>>,
+        absence: Duration,
+    ) {
+        loop {
+            let device = match inner.wait_for_device().await {
+                Ok(device) => device,
+                Err(e) => {
+                    let fatal = e.is_fatal();
+                    if sender.send(Err(e)).await.is_err() || fatal {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            let mut seen = seen.lock().unwrap();
+            // the sweep may not have caught up with a departure yet
+            if matches!(seen.get(&device), Some(sighting) if now.duration_since(sighting.last_seen) >= absence)
+            {
+                seen.remove(&device);
+                let _ = events.send(PresenceEvent::Departed(device.clone()));
+            }
+            let sighting = seen.entry(device.clone()).or_insert_with(|| {
+                debug!("Device {:?} arrived", device);
+                let _ = events.send(PresenceEvent::Arrived(device.clone()));
+                Sighting {
+                    last_seen: now,
+                    reported: false,
+                }
+            });
+            sighting.last_seen = now;
+
+            if sighting.reported {
+                continue;
+            }
+            // marked as reported once taken, a sighting that does not fit is retried at the next one
+            match sender.try_send(Ok(device)) {
+                Ok(()) | Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Closed(_)) => break,
+            }
+        }
+    }
+
+    /// Removes devices that were not seen for `absence`
+    async fn sweep(
+        seen: Sightings<D::Device>,
+        events: broadcast::Sender<PresenceEvent<D::Device>>,
+        absence: Duration,
+    ) {
+        loop {
+            let next = {
+                let mut seen = seen.lock().unwrap();
+                let now = Instant::now();
+                seen.retain(|device, sighting| {
+                    let present = now.duration_since(sighting.last_seen) < absence;
+                    if !present {
+                        debug!("Device {:?} departed", device);
+                        let _ = events.send(PresenceEvent::Departed(device.clone()));
+                    }
+                    present
+                });
+                seen.values()
+                    .map(|sighting| sighting.last_seen + absence)
+                    .min()
+                    .unwrap_or(now + absence)
+            };
+            sleep_until(next).await;
+        }
+    }
+
+    /// Marks a queued detection as reported,
+    /// `false` if the device departed since or was already reported
+    fn take(&self, device: &D::Device) -> bool {
+        match self.seen.lock().unwrap().get_mut(device) {
+            Some(sighting) if !sighting.reported => {
+                sighting.reported = true;
+                true
+            }
+            Some(_) => false,
+            None => {
+                debug!("Dropping detection of departed device {:?}", device);
+                false
+            }
+        }
+    }
+
+    /// Subscribe to arrivals and departures
+    pub fn subscribe(&self) -> broadcast::Receiver<PresenceEvent<D::Device>> {
+        self.events.subscribe()
+    }
+
+    /// Devices currently in range
+    pub fn present(&self) -> Vec<D::Device> {
+        self.seen.lock().unwrap().keys().cloned().collect()
+    }
+
+    pub fn is_present(&self, device: &D::Device) -> bool {
+        self.seen.lock().unwrap().contains_key(device)
+    }
+
+    /// When the device was last seen by the inner detector, `None` if it is not in range
+    pub fn last_seen(&self, device: &D::Device) -> Option<Instant> {
+        self.seen
+            .lock()
+            .unwrap()
+            .get(device)
+            .map(|sighting| sighting.last_seen)
+    }
+}
+
+impl<D: Detector> Drop for Presence<D> {
+    fn drop(&mut self) {
+        self.watch.lock().unwrap().abort();
+        self.sweep.abort();
+    }
+}
+
+#[async_trait]
+impl<D> Detector for Presence<D>
+where
+    D: Detector + Send + Sync + 'static,
+    D::Device: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    type Device = D::Device;
+    type DetectorError = D::DetectorError;
+
+    async fn wait_for_device(&self) -> Result<Self::Device, Self::DetectorError> {
+        let mut detections = self.detections.lock().await;
+        loop {
+            let device = match detections.recv().await {
+                Some(detection) => detection?,
+                // never closed, a sender is kept to revive the watch
+                None => futures::future::pending().await,
+            };
+
+            if self.take(&device) {
+                return Ok(device);
+            }
+        }
+    }
+
+    fn source(&self, device: &Self::Device) -> String {
+        self.inner.source(device)
+    }
+
+    async fn wait_for_departure(&self, device: &Self::Device) {
+        // subscribe first, the device may leave while checking
+        let mut events = self.events.subscribe();
+        if !self.is_present(device) {
+            return;
+        }
+
+        loop {
+            match events.recv().await {
+                Ok(PresenceEvent::Departed(departed)) if departed == *device => return,
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) if !self.is_present(device) => return,
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => future::pending().await,
+            }
+        }
+    }
+
+    async fn restart(&self) -> Result<(), Self::DetectorError> {
+        // a running watch keeps the inner detector busy, one stopped at a fatal error is revived
+        self.watch.lock().unwrap().abort();
+        let result = self.inner.restart().await;
+
+        *self.watch.lock().unwrap() = tokio::spawn(Self::watch(
+            self.inner.clone(),
+            self.seen.clone(),
+            self.events.clone(),
+            self.sender.clone(),
+            self.absence,
+        ));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::{sleep, timeout};
+
+    use super::{Presence, Race};
+    use crate::{
+        combinators::Either,
+        interfaces::services::{Detector, ServiceError},
+        testing::{DetectorCall, MockDetector, MockDevice, MockError},
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn race_reports_the_first_detection() {
+        let left = MockDetector::new().detect(Duration::from_secs(5), MockDevice::new("phone"));
+        let right = MockDetector::new().detect(Duration::from_secs(1), MockDevice::new("tablet"));
+        let race = Race::new(left, right);
+
+        match race.wait_for_device().await.unwrap() {
+            Either::Right(device) => assert_eq!(device, MockDevice::new("tablet")),
+            Either::Left(device) => panic!("{} detected first", device),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn race_keeps_waiting_after_one_side_failed() {
+        let left = MockDetector::new().fail(Duration::from_secs(1), MockError::fatal("gone"));
+        let right = MockDetector::new().detect(Duration::from_secs(3), MockDevice::new("phone"));
+        let race = Race::new(left, right);
+
+        assert_eq!(
+            race.wait_for_device().await.unwrap().into_inner(),
+            MockDevice::new("phone")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn race_fails_once_both_sides_failed() {
+        let left = MockDetector::<MockDevice>::new()
+            .fail(Duration::from_secs(1), MockError::fatal("gone"));
+        let right =
+            MockDetector::<MockDevice>::new().fail(Duration::from_secs(2), MockError::new("busy"));
+        let race = Race::new(left, right);
+
+        let error = race.wait_for_device().await.unwrap_err();
+        // the remaining side may still recover
+        assert!(!error.is_fatal());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn race_waits_for_departure_on_the_detecting_side() {
+        let left = MockDetector::new().depart(Duration::from_secs(1));
+        let right = MockDetector::new();
+        let race = Race::new(left, right);
+        let phone = MockDevice::new("phone");
+
+        assert!(timeout(
+            Duration::from_secs(60),
+            race.wait_for_departure(&Either::Right(phone.clone()))
+        )
+        .await
+        .is_err());
+        assert!(timeout(
+            Duration::from_secs(60),
+            race.wait_for_departure(&Either::Left(phone))
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn arrival_is_reported_once() {
+        let phone = MockDevice::new("phone");
+        let inner = MockDetector::new()
+            .detect(Duration::from_secs(1), phone.clone())
+            .detect(Duration::from_secs(1), phone.clone());
+        let presence = Presence::new(inner, Duration::from_secs(10));
+
+        assert_eq!(presence.wait_for_device().await.unwrap(), phone);
+        assert!(timeout(Duration::from_secs(5), presence.wait_for_device())
+            .await
+            .is_err());
+        assert!(presence.is_present(&phone));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queued_detection_of_departed_device_is_dropped() {
+        let phone = MockDevice::new("phone");
+        let inner = MockDetector::new().detect(Duration::from_secs(1), phone.clone());
+        let presence = Presence::new(inner, Duration::from_secs(10));
+
+        // nobody waits while the phone arrives and leaves again
+        sleep(Duration::from_secs(3600)).await;
+
+        assert!(!presence.is_present(&phone));
+        assert!(timeout(Duration::from_secs(60), presence.wait_for_device())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restart_revives_polling_after_fatal_error() {
+        let phone = MockDevice::new("phone");
+        let inner = MockDetector::new()
+            .fail(Duration::from_secs(1), MockError::fatal("adapter gone"))
+            .detect(Duration::from_secs(1), phone.clone());
+        let presence = Presence::new(inner.clone(), Duration::from_secs(10));
+
+        assert!(presence.wait_for_device().await.is_err());
+        assert!(timeout(Duration::from_secs(60), presence.wait_for_device())
+            .await
+            .is_err());
+
+        presence.restart().await.unwrap();
+        assert_eq!(presence.wait_for_device().await.unwrap(), phone);
+        assert!(inner
+            .calls()
+            .iter()
+            .any(|(_, call)| *call == DetectorCall::Restart));
+    }
+}
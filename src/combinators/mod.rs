@@ -0,0 +1,77 @@
+use std::hash::{Hash, Hasher};
+
+use derive_more::Display;
+
+use crate::access::{Access, Restricted};
+
+pub mod authenticator;
+pub mod detector;
+
+/// Unifies the devices of two combined services
+///
+/// Devices of the same type are equal regardless of the side they come from,
+/// e.g. a device blocked after one detector found it stays blocked when the other finds it.
+#[derive(Debug, Display, Clone)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<T> Either<T, T> {
+    /// The device if both sides share the same type
+    pub fn inner(&self) -> &T {
+        match self {
+            Either::Left(inner) | Either::Right(inner) => inner,
+        }
+    }
+
+    /// Unwraps the device if both sides share the same type
+    pub fn into_inner(self) -> T {
+        match self {
+            Either::Left(inner) | Either::Right(inner) => inner,
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for Either<T, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner() == other.inner()
+    }
+}
+
+impl<T: Eq> Eq for Either<T, T> {}
+
+impl<T: Hash> Hash for Either<T, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner().hash(state);
+    }
+}
+
+impl<L: Restricted, R: Restricted> Restricted for Either<L, R> {
+    fn access(&self) -> &Access {
+        match self {
+            Either::Left(device) => device.access(),
+            Either::Right(device) => device.access(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::Either;
+    use crate::testing::MockDevice;
+
+    #[test]
+    fn sides_of_the_same_device_are_equal() {
+        let phone = MockDevice::new("phone");
+        let left = Either::Left(phone.clone());
+        let right = Either::Right(phone);
+
+        assert_eq!(left, right);
+        assert_ne!(left, Either::Right(MockDevice::new("tablet")));
+        let devices = vec![left, right].into_iter().collect::<HashSet<_>>();
+        assert_eq!(devices.len(), 1);
+    }
+}
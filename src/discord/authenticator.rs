@@ -1,35 +1,91 @@
 use async_trait::async_trait;
-use doorman::interfaces::services::{self, AuthenticateResult, ServiceError};
-use serenity::Error as SerenityError;
-use core::time;
-use std::{fmt::Display, marker::PhantomData, time::Duration};
+use futures::future;
+use doorman::interfaces::services::{
+    self, AuthenticateResult, Notice, ServiceError, Withdrawal,
+};
+use serenity::{
+    model::{channel::Message, user::User},
+    Error as SerenityError,
+};
+use std::{
+    fmt::Display,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use thiserror::Error;
 
-use super::client::{Client, Initialized};
+use super::client::{Client, ClientError, Initialized};
 
 #[derive(Debug, Error)]
 pub enum AuthorizationError {
     #[error("Client Error: {0}")]
     Client(#[from] SerenityError),
+    #[error("Reconnect Error: {0}")]
+    Reconnect(#[from] ClientError),
 }
 
 impl ServiceError for AuthorizationError {}
 
-pub struct DiscordAuth<'a, D> {
-    client: &'a Client<Initialized>,
+/// Asks an approver by direct message
+///
+/// Every door needs its own instance, several instances may share a client.
+pub struct DiscordAuth<D> {
+    client: Arc<Client<Initialized>>,
+    /// The approver asked to authenticate
+    user: User,
+    /// The door named in prompts
+    door: Option<String>,
+    /// The prompt currently waiting for a reaction
+    pending: Mutex<Option<Message>>,
+    /// The most recent alert of an unlocked door, acknowledged with 👌
+    alert: Mutex<Option<Message>>,
+    /// How long a device is allowed without asking when reacting with ⏳
+    grant: Duration,
     device: PhantomData<D>,
 }
 
-impl<'a, D> DiscordAuth<'a, D> {
-    pub fn new(client: &'a Client<Initialized>) -> Self {
+impl<D> DiscordAuth<D> {
+    pub fn new(client: Arc<Client<Initialized>>) -> Self {
+        let user = client.user.clone();
+        Self::for_user(client, user)
+    }
+
+    /// Ask a different user than the one the client was started with
+    pub fn for_user(client: Arc<Client<Initialized>>, user: User) -> Self {
         let device = PhantomData;
+        let pending = Mutex::new(None);
+
+        Self {
+            client,
+            user,
+            door: None,
+            pending,
+            alert: Mutex::new(None),
+            grant: Duration::from_secs(60 * 60),
+            device,
+        }
+    }
+
+    /// How long a device is allowed without asking when reacting with ⏳, an hour by default
+    pub fn with_grant(mut self, grant: Duration) -> Self {
+        self.grant = grant;
+        self
+    }
 
-        Self { client, device }
+    /// Name the door in prompts, approvers of several doors can tell them apart
+    pub fn at_door(mut self, door: impl Into<String>) -> Self {
+        self.door = Some(door.into());
+        self
+    }
+
+    fn door(&self) -> &str {
+        self.door.as_deref().unwrap_or("your door")
     }
 }
 
 #[async_trait]
-impl<D: Send + Sync + Display> services::Authenticate for DiscordAuth<'_, D> {
+impl<D: Send + Sync + Display> services::Authenticate for DiscordAuth<D> {
     type Device = D;
     type AuthenticateError = AuthorizationError;
 
@@ -38,36 +94,128 @@ impl<D: Send + Sync + Display> services::Authenticate for DiscordAuth<'_, D> {
         device: &Self::Device,
         timeout: Option<Duration>,
     ) -> Result<services::AuthenticateResult, Self::AuthenticateError> {
-        let ctx = self.client.state.ctx.clone();
+        let ctx = self.client.ctx();
         let message = self
-            .client
             .user
             .direct_message(&*ctx, |m| {
                 m.content(format!(
-                    "Device close to your door detected: {}\nOpen the door?",
-                    device
+                    "Device close to {} detected: {}\nOpen the door?\n\
+                     👍 open, ⏳ open and don't ask for {} min, 🚷 deny, ⛔ deny and block",
+                    self.door(),
+                    device,
+                    self.grant.as_secs() / 60
                 ));
-                m.reactions(['👍', '🚷'].iter().cloned())
+                m.reactions(['👍', '⏳', '🚷', '⛔'].iter().cloned())
             })
             .await?;
+        *self.pending.lock().unwrap() = Some(message.clone());
 
         let mut collect_reaction = message.await_reaction(&*ctx);
         if let Some(timeout) = timeout {
             collect_reaction = collect_reaction.timeout(timeout);
         }
 
-        if let Some(reaction) = collect_reaction.await {
+        let reaction = collect_reaction.await;
+        self.pending.lock().unwrap().take();
+
+        if let Some(reaction) = reaction {
             let react = &reaction.as_inner_ref().emoji;
 
             return match react.as_data().as_str() {
                 "👍" => Ok(AuthenticateResult::Allow),
+                "⏳" => Ok(AuthenticateResult::AllowFor(self.grant)),
+                "⛔" => Ok(AuthenticateResult::DenyAndBlock),
                 _ => Ok(AuthenticateResult::Deny),
             };
         } else {
             let _ = message.reply(&*ctx, "Invalidated.").await?;
 
-            return Ok(AuthenticateResult::Deny);
+            return Ok(AuthenticateResult::Timeout);
         }
         // map_err(AuthorizationError::Client)
     }
+
+    async fn inform(&self, notice: &Notice<'_, Self::Device>) -> Result<(), Self::AuthenticateError> {
+        let ctx = self.client.ctx();
+        let content = match notice {
+            Notice::WouldOpen(device) => {
+                format!("Dry run: would have opened {} for {}", self.door(), device)
+            }
+            Notice::AutoAllowed(device) => {
+                format!("Opened {} for trusted device {}", self.door(), device)
+            }
+            Notice::LeftUnlocked(open_for, escalated) => {
+                let content = format!(
+                    "{}Please lock {}, it has been unlocked for {} min\n👌 I'm on it",
+                    if *escalated { "Nobody responded: " } else { "" },
+                    self.door(),
+                    open_for.as_secs() / 60
+                );
+                let message = self
+                    .user
+                    .direct_message(&*ctx, |m| {
+                        m.content(content);
+                        m.reactions(['👌'].iter().cloned())
+                    })
+                    .await?;
+                *self.alert.lock().unwrap() = Some(message);
+                return Ok(());
+            }
+        };
+
+        self.user
+            .direct_message(&*ctx, |m| m.content(content))
+            .await?;
+        Ok(())
+    }
+
+    async fn acknowledged(&self) -> Result<(), Self::AuthenticateError> {
+        let alert = self.alert.lock().unwrap().clone();
+        let ctx = self.client.ctx();
+
+        let reaction = match alert {
+            Some(alert) => {
+                alert
+                    .await_reaction(&*ctx)
+                    .author_id(self.user.id)
+                    .filter(|reaction| reaction.as_ref().emoji.as_data() == "👌")
+                    .await
+            }
+            None => None,
+        };
+
+        match reaction {
+            Some(_) => Ok(()),
+            None => future::pending().await,
+        }
+    }
+
+    async fn restart(&self) -> Result<(), Self::AuthenticateError> {
+        Ok(self.client.restart().await?)
+    }
+
+    fn approver(&self) -> Option<String> {
+        Some(self.user.tag())
+    }
+
+    async fn withdraw(&self, reason: Withdrawal) -> Result<(), Self::AuthenticateError> {
+        let message = self.pending.lock().unwrap().take();
+
+        if let Some(mut message) = message {
+            let ctx = self.client.ctx();
+            match reason {
+                // a late answer would open the door for nobody, make that obvious on the prompt
+                Withdrawal::Left => {
+                    let content = format!("{}\n{}", message.content, reason);
+                    message.edit(&*ctx, |m| m.content(content)).await?;
+                }
+                _ => {
+                    message
+                        .reply(&*ctx, format!("Withdrawn: {}", reason))
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
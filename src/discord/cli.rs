@@ -8,4 +8,12 @@ pub struct Args {
     /// Discord Bot Token
     #[clap(short, long, env = "DISCORD_TOKEN")]
     pub token: String,
+
+    /// Discord UserIDs asked in turn if the previous approver does not answer within the authorization timeout
+    #[clap(long)]
+    pub escalate: Vec<u64>,
+
+    /// How long a device is let in without asking after an approver reacted with ⏳ (in sec)
+    #[clap(long, env = "DISCORD_GRANT", default_value = "3600")]
+    pub grant: u64,
 }
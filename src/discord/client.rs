@@ -1,12 +1,12 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as SyncMutex};
 
 use async_trait::async_trait;
-use futures::lock::Mutex;
-use log::{debug, error, info};
+use futures::{lock::Mutex, FutureExt};
+use log::{debug, error, info, warn};
 use serenity::{
     client::{Context, EventHandler},
     framework::StandardFramework,
-    model::prelude::Ready,
+    model::{prelude::Ready, user::User},
     Client as SerenityClient,
 };
 use thiserror::Error;
@@ -21,7 +21,8 @@ struct ReadyHandler(tokio::sync::mpsc::Sender<Context>);
 impl EventHandler for ReadyHandler {
     async fn ready(&self, ctx: Context, _: Ready) {
         info!("Connected");
-        self.0.send(ctx).await;
+        // nobody waits for the context of a reconnect of a running client
+        let _ = self.0.try_send(ctx);
     }
 }
 #[derive(Error, Debug)]
@@ -36,15 +37,24 @@ pub struct Uninitialized {
 }
 impl ClientState for Uninitialized {}
 
+type Connection = JoinHandle<Result<(), serenity::Error>>;
+
+/// The running gateway connection
+struct Gateway {
+    ready: Receiver<Context>,
+    /// `None` once the connection ended and could not be restarted
+    handle: Option<Connection>,
+}
+
 pub struct Initialized {
-    pub(crate) ctx: Arc<Context>,
-    handle: JoinHandle<Result<(), serenity::Error>>,
+    ctx: SyncMutex<Arc<Context>>,
+    gateway: Mutex<Gateway>,
 }
 impl ClientState for Initialized {}
 
 pub struct Client<S: ClientState> {
     client: Arc<Mutex<serenity::Client>>,
-    pub(crate) user: serenity::model::user::User,
+    pub(crate) user: User,
     pub(crate) state: S,
 }
 
@@ -70,25 +80,75 @@ impl Client<Uninitialized> {
     }
 
     pub async fn run(mut self) -> Result<Client<Initialized>, ClientError> {
-        let client = self.client.clone();
-        let handle = tokio::spawn(async move { client.lock().await.start().await });
+        let (ctx, handle) = connect(&self.client, &mut self.state.ready).await?;
+
+        Ok(Client {
+            client: self.client,
+            user: self.user,
+            state: Initialized {
+                ctx: SyncMutex::new(ctx),
+                gateway: Mutex::new(Gateway {
+                    ready: self.state.ready,
+                    handle: Some(handle),
+                }),
+            },
+        })
+    }
+}
 
-        if let Some(ctx) = self.state.ready.recv().await.map(Arc::new) {
-            let initialized: Client<Initialized> = Client {
-                client: self.client,
-                user: self.user,
-                state: Initialized { ctx, handle },
-            };
+impl Client<Initialized> {
+    pub async fn get_user(&self, user_id: u64) -> Result<User, serenity::Error> {
+        self.ctx().http.get_user(user_id).await
+    }
 
-            debug!("Context Received");
+    /// The context of the current connection
+    pub(crate) fn ctx(&self) -> Arc<Context> {
+        self.state.ctx.lock().unwrap().clone()
+    }
 
-            return Ok(initialized);
-        } else {
-            handle.abort();
-            error!("Could not retrieve context");
-            return Err(ClientError::ContextMissing);
+    /// Connects again if the gateway connection ended
+    ///
+    /// A running connection reconnects on its own and is kept.
+    pub async fn restart(&self) -> Result<(), ClientError> {
+        let mut gateway = self.state.gateway.lock().await;
+
+        if let Some(handle) = gateway.handle.as_mut() {
+            match handle.now_or_never() {
+                None => return Ok(()),
+                Some(Ok(Ok(()))) => warn!("Connection ended"),
+                Some(Ok(Err(e))) => warn!("Connection ended: {}", e),
+                Some(Err(e)) => warn!("Connection ended: {}", e),
+            }
         }
+        gateway.handle = None;
+
+        // contexts of the previous connection
+        while let Some(Some(_)) = gateway.ready.recv().now_or_never() {}
+
+        info!("Reconnecting");
+        let (ctx, handle) = connect(&self.client, &mut gateway.ready).await?;
+        *self.state.ctx.lock().unwrap() = ctx;
+        gateway.handle = Some(handle);
+        Ok(())
     }
 }
 
-impl Client<Initialized> {}
+/// Starts the gateway connection and waits for its context
+async fn connect(
+    client: &Arc<Mutex<SerenityClient>>,
+    ready: &mut Receiver<Context>,
+) -> Result<(Arc<Context>, Connection), ClientError> {
+    let client = client.clone();
+    let mut handle = tokio::spawn(async move { client.lock().await.start().await });
+
+    tokio::select! {
+        Some(ctx) = ready.recv() => {
+            debug!("Context Received");
+            Ok((Arc::new(ctx), handle))
+        }
+        _ = &mut handle => {
+            error!("Could not retrieve context");
+            Err(ClientError::ContextMissing)
+        }
+    }
+}
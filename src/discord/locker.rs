@@ -1,40 +1,43 @@
 use async_trait::async_trait;
-use doorman::interfaces::services::{self, ServiceError};
+use doorman::interfaces::services::{self, HoldOpen, ServiceError};
 use futures::{StreamExt, future};
 use serenity::{Error as SerenityError, model::channel::Message};
-use std::{fmt::Display, marker::PhantomData, time::Duration};
+use std::sync::Arc;
 use thiserror::Error;
 
-use super::client::{Client, Initialized};
+use super::client::{Client, ClientError, Initialized};
 
 #[derive(Debug, Error)]
 pub enum LockerError {
     #[error("Client Error: {0}")]
     Client(#[from] SerenityError),
 
+    #[error("Reconnect Error: {0}")]
+    Reconnect(#[from] ClientError),
+
     #[error("Failed to lock")]
     LockFailure
 }
 
 impl ServiceError for LockerError {}
 
-pub struct DiscordLocker<'a> {
-    client: &'a Client<Initialized>,
+pub struct DiscordLocker {
+    client: Arc<Client<Initialized>>,
 }
 
-impl<'a> DiscordLocker<'a> {
-    pub fn new(client: &'a Client<Initialized>) -> Self {
+impl DiscordLocker {
+    pub fn new(client: Arc<Client<Initialized>>) -> Self {
         Self { client }
     }
 }
 
 #[async_trait]
-impl services::Locker for DiscordLocker<'_> {
+impl services::Locker for DiscordLocker {
 
     type LockerError = LockerError;
 
     async fn wait_for_lock(&self) -> Result<(), Self::LockerError> {
-        let ctx = self.client.state.ctx.clone();
+        let ctx = self.client.ctx();
         let message = self
             .client
             .user
@@ -55,7 +58,7 @@ impl services::Locker for DiscordLocker<'_> {
     }
 
     async fn confirm_lock(&self) -> Result<(), Self::LockerError> {
-        let ctx = self.client.state.ctx.clone();
+        let ctx = self.client.ctx();
 
         self
             .client
@@ -68,5 +71,31 @@ impl services::Locker for DiscordLocker<'_> {
         Ok(())
     }
 
+    async fn hold_open(&self) -> Result<HoldOpen, Self::LockerError> {
+        let ctx = self.client.ctx();
+        let message = self
+            .client
+            .user
+            .direct_message(&*ctx, |m| {
+                m.content("Door relocks automatically soon. React to keep it open longer (⏳) or until locked manually (🔓)".to_string());
+                m.reactions(['⏳', '🔓'].iter().cloned())
+            })
+            .await?;
+
+        let reaction = message.await_reaction(&*ctx).filter(
+            |reaction| {
+                let react = &reaction.as_ref().emoji;
+                return matches!(react.as_data().as_str(), "⏳" | "🔓");
+            }
+        ).await.ok_or(LockerError::LockFailure)?;
 
+        match reaction.as_inner_ref().emoji.as_data().as_str() {
+            "🔓" => Ok(HoldOpen::Cancel),
+            _ => Ok(HoldOpen::Extend),
+        }
+    }
+
+    async fn restart(&self) -> Result<(), Self::LockerError> {
+        Ok(self.client.restart().await?)
+    }
 }
@@ -1,8 +1,15 @@
-use std::{collections::HashMap, error::Error, fmt::Debug, time::Duration};
+use std::{collections::HashMap, error::Error, fmt::Debug, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
+use derive_more::Display;
+use serde::{Serialize, Serializer};
 
-pub trait ServiceError: Error + std::fmt::Debug + Send + Sync {}
+pub trait ServiceError: Error + std::fmt::Debug + Send + Sync {
+    /// Whether the service cannot recover from this error, e.g. by retrying
+    fn is_fatal(&self) -> bool {
+        false
+    }
+}
 
 #[async_trait]
 pub trait Detector {
@@ -10,7 +17,27 @@ pub trait Detector {
     type DetectorError: ServiceError;
 
     /// Detect a device asynchronously
-    async fn wait_for_device(&self) -> Result<&Self::Device, Self::DetectorError>;
+    async fn wait_for_device(&self) -> Result<Self::Device, Self::DetectorError>;
+
+    /// Names the detector that detected the device, e.g. for audit logs
+    fn source(&self, _device: &Self::Device) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
+    /// Resolves once the device went out of range, e.g. to cancel a pending authentication
+    ///
+    /// Detectors that do not track presence never resolve.
+    async fn wait_for_departure(&self, _device: &Self::Device)
+    where
+        Self::Device: Sync,
+    {
+        futures::future::pending().await
+    }
+
+    /// Bring the detector back into a working state after an error
+    async fn restart(&self) -> Result<(), Self::DetectorError> {
+        Ok(())
+    }
 }
 
 pub trait Registry {
@@ -26,6 +53,7 @@ pub trait Registry {
     ) -> Result<(), Self::RegistryError>;
 
     /// Imports a map of devices
+    #[allow(clippy::wrong_self_convention)]
     fn from_map<I: Into<Self::Ident>, D: Into<Self::Device> + Clone>(
         &mut self,
         devices: HashMap<I, D>,
@@ -47,6 +75,7 @@ pub trait Registry {
 
     /// Imports a list of devices
     /// This works as long as an identifier can be derived from the Device
+    #[allow(clippy::wrong_self_convention)]
     fn from_list<D: Into<Self::Ident> + Into<Self::Device> + Clone>(
         &mut self,
         devices: impl IntoIterator<Item = D>,
@@ -84,15 +113,73 @@ where
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AuthenticateResult {
+    /// Open the door this once
     Allow,
+    /// Open the door and do not ask again for the given duration
+    AllowFor(#[serde(serialize_with = "as_secs")] Duration),
     Deny,
+    /// Deny and record why
+    DenyWithReason(String),
+    /// Deny and lock the device out until the lockout is cleared
+    DenyAndBlock,
+    /// No decision was made within the given timeout
+    Timeout,
+}
+
+impl AuthenticateResult {
+    /// Whether the door is opened
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, AuthenticateResult::Allow | AuthenticateResult::AllowFor(_))
+    }
+
+    /// Whether access is denied, a timeout is neither allowed nor denied
+    pub fn is_denied(&self) -> bool {
+        matches!(
+            self,
+            AuthenticateResult::Deny
+                | AuthenticateResult::DenyWithReason(_)
+                | AuthenticateResult::DenyAndBlock
+        )
+    }
+}
+
+fn as_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+/// Why a pending authentication request is withdrawn
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum Withdrawal {
+    #[display(fmt = "Decided by another approver")]
+    Decided,
+    #[display(fmt = "Doorman is shutting down")]
+    Shutdown,
+    #[display(fmt = "Visitor left")]
+    Left,
+}
+
+/// Information for approvers that needs no answer
+#[derive(Debug)]
+pub enum Notice<'a, Device> {
+    /// Dry run: the door would have been opened for the device
+    WouldOpen(&'a Device),
+    /// The door was opened for a trusted device without asking
+    AutoAllowed(&'a Device),
+    /// The door has been unlocked for the given duration,
+    /// escalated once earlier alerts were not responded to
+    LeftUnlocked(Duration, bool),
 }
 
+/// Asks approvers whether a detected device may enter
+///
+/// Authenticators may keep track of the request in progress, e.g. to withdraw it,
+/// an instance serves a single door.
 #[async_trait]
 pub trait Authenticate {
-    type Device;
+    type Device: Sync;
     type AuthenticateError: ServiceError;
 
     /// request an authentiation
@@ -101,6 +188,36 @@ pub trait Authenticate {
         device: &Self::Device,
         timeout: Option<Duration>,
     ) -> Result<AuthenticateResult, Self::AuthenticateError>;
+
+    /// Withdraw a pending authentication request
+    ///
+    /// Called once a request was abandoned before this authenticator answered,
+    /// implementations should invalidate any prompt still waiting for an answer.
+    async fn withdraw(&self, _reason: Withdrawal) -> Result<(), Self::AuthenticateError> {
+        Ok(())
+    }
+
+    /// Inform approvers about something that needs no answer
+    async fn inform(&self, _notice: &Notice<'_, Self::Device>) -> Result<(), Self::AuthenticateError> {
+        Ok(())
+    }
+
+    /// Resolves once an approver acknowledged the most recent [`Notice::LeftUnlocked`] alert
+    ///
+    /// Only polled after an alert was sent, the default never resolves.
+    async fn acknowledged(&self) -> Result<(), Self::AuthenticateError> {
+        futures::future::pending().await
+    }
+
+    /// Names the approver who made the most recent decision, if known
+    fn approver(&self) -> Option<String> {
+        None
+    }
+
+    /// Bring the authenticator back into a working state after an error
+    async fn restart(&self) -> Result<(), Self::AuthenticateError> {
+        Ok(())
+    }
 }
 
 pub trait Actuator {
@@ -108,6 +225,33 @@ pub trait Actuator {
 
     /// Actuate the opening mechanism
     fn open(&mut self) -> Result<(), Self::ActuatorError>;
+
+    /// Bring the actuator back into a working state after an error
+    fn restart(&mut self) -> Result<(), Self::ActuatorError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+pub trait Keypad {
+    type KeypadError: ServiceError;
+
+    /// Wait for a PIN to be entered, `None` if nothing was entered in time
+    async fn read_pin(&self, timeout: Option<Duration>) -> Result<Option<String>, Self::KeypadError>;
+
+    /// Bring the keypad back into a working state after an error
+    async fn restart(&self) -> Result<(), Self::KeypadError> {
+        Ok(())
+    }
+}
+
+/// Requests to change the automatic relock of an open door
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldOpen {
+    /// Restart the relock timer
+    Extend,
+    /// Keep the door open until it is locked through the locker
+    Cancel,
 }
 
 #[async_trait]
@@ -119,4 +263,96 @@ pub trait Locker {
 
     /// Confirm lock to the user
     async fn confirm_lock(&self) -> Result<(), Self::LockerError>;
+
+    /// Await a request to extend or cancel the automatic relock
+    ///
+    /// Only polled while a relock timer is running, the default never makes a request.
+    async fn hold_open(&self) -> Result<HoldOpen, Self::LockerError> {
+        futures::future::pending().await
+    }
+
+    /// Bring the locker back into a working state after an error
+    async fn restart(&self) -> Result<(), Self::LockerError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Detector + Send + Sync + ?Sized> Detector for Arc<T> {
+    type Device = T::Device;
+    type DetectorError = T::DetectorError;
+
+    async fn wait_for_device(&self) -> Result<Self::Device, Self::DetectorError> {
+        (**self).wait_for_device().await
+    }
+
+    fn source(&self, device: &Self::Device) -> String {
+        (**self).source(device)
+    }
+
+    async fn wait_for_departure(&self, device: &Self::Device)
+    where
+        Self::Device: Sync,
+    {
+        (**self).wait_for_departure(device).await
+    }
+
+    async fn restart(&self) -> Result<(), Self::DetectorError> {
+        (**self).restart().await
+    }
+}
+
+#[async_trait]
+impl<T: Authenticate + Send + Sync + ?Sized> Authenticate for Arc<T> {
+    type Device = T::Device;
+    type AuthenticateError = T::AuthenticateError;
+
+    async fn authenticate(
+        &self,
+        device: &Self::Device,
+        timeout: Option<Duration>,
+    ) -> Result<AuthenticateResult, Self::AuthenticateError> {
+        (**self).authenticate(device, timeout).await
+    }
+
+    async fn withdraw(&self, reason: Withdrawal) -> Result<(), Self::AuthenticateError> {
+        (**self).withdraw(reason).await
+    }
+
+    async fn inform(&self, notice: &Notice<'_, Self::Device>) -> Result<(), Self::AuthenticateError> {
+        (**self).inform(notice).await
+    }
+
+    async fn acknowledged(&self) -> Result<(), Self::AuthenticateError> {
+        (**self).acknowledged().await
+    }
+
+    fn approver(&self) -> Option<String> {
+        (**self).approver()
+    }
+
+    async fn restart(&self) -> Result<(), Self::AuthenticateError> {
+        (**self).restart().await
+    }
+}
+
+#[async_trait]
+impl<T: Locker + Send + Sync + ?Sized> Locker for Arc<T> {
+    type LockerError = T::LockerError;
+
+    async fn wait_for_lock(&self) -> Result<(), Self::LockerError> {
+        (**self).wait_for_lock().await
+    }
+
+    async fn confirm_lock(&self) -> Result<(), Self::LockerError> {
+        (**self).confirm_lock().await
+    }
+
+    async fn hold_open(&self) -> Result<HoldOpen, Self::LockerError> {
+        (**self).hold_open().await
+    }
+
+    async fn restart(&self) -> Result<(), Self::LockerError> {
+        (**self).restart().await
+    }
 }
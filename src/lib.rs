@@ -1,3 +1,11 @@
+pub mod access;
+pub mod audit;
+pub mod combinators;
 pub mod interfaces;
 pub mod manager;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod registry;
+pub mod supervisor;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
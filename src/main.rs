@@ -1,25 +1,54 @@
 #[cfg(feature = "discord_base")]
 mod discord;
-use std::{path::PathBuf, time::Duration};
+use std::{
+    fmt::Display,
+    fs::File,
+    hash::Hash,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 #[cfg(feature = "discord_base")]
 use discord::{authenticator::DiscordAuth, client};
+#[cfg(feature = "discord_base")]
+use doorman::{combinators::authenticator::Escalation, interfaces::services::AuthenticateResult};
 
 #[cfg(feature = "bluetooth")]
 mod bluetooth;
 #[cfg(feature = "bluetooth")]
-use bluetooth::{detector::BluetoothDetector, device::BluetoothDevice};
+use bluetooth::{
+    detector::BluetoothDetector,
+    device::{Address, BluetoothDevice},
+};
 
 use clap::Clap;
 use doorman::{interfaces::services::Registry as RegistryTrait, manager};
-use doorman::{manager::Manager, registry::Registry};
-use log::{debug, LevelFilter};
-use simple::{actuator, authenticator, device::SimpleDevice};
-
-use crate::{
-    discord::locker::DiscordLocker,
-    simple::{detector::Detector, locker::Locker},
+use doorman::{
+    access,
+    audit::{self, AuditLog},
+    manager::{Backoff, Manager, Recovery, RecoveryConfig, RetryPolicy},
+    registry::Registry,
+    supervisor::Supervisor,
+};
+use log::{debug, error, info, LevelFilter};
+#[cfg(feature = "bluetooth")]
+use doorman::combinators::detector::{PresenceEvent, Presence, Race};
+#[cfg(feature = "metrics")]
+use doorman::metrics::Metrics;
+#[cfg(not(feature = "bluetooth"))]
+use simple::device::SimpleDevice;
+use simple::{actuator, authenticator, input::Input};
+use tokio::signal::{
+    ctrl_c,
+    unix::{signal, Signal, SignalKind},
 };
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "discord_base")]
+use discord::locker::DiscordLocker;
+use simple::locker::Locker;
 
 mod simple;
 
@@ -41,18 +70,68 @@ struct ManagerConfig {
     #[clap(short, long, env = "AUTH_TIMEOUT")]
     timeout: Option<u64>,
 
-    /// Time between authorization attempts (in sec)
+    /// Time between authorization attempts of a device, doubled after every denial (in sec)
     #[clap(short, long, env = "COOLDOWN_TIMEOUT", default_value="30")]
     cooldown: u64,
+
+    /// Maximum time between authorization attempts of a device (in sec)
+    #[clap(long, env = "MAX_COOLDOWN_TIMEOUT", default_value="3600")]
+    max_cooldown: u64,
+
+    /// How long denials of a device are remembered (in sec)
+    #[clap(long, env = "DENIAL_WINDOW", default_value="86400")]
+    denial_window: u64,
+
+    /// Ignore a device after this many denials within the denial window
+    #[clap(long, env = "LOCKOUT_AFTER")]
+    lockout_after: Option<usize>,
+
+    /// Run the full flow but never open the door
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Inform approvers when the door was opened for a trusted device
+    #[clap(long)]
+    report_trusted: bool,
+
+    /// Retry failed services this many times before giving up
+    #[clap(long, env = "RETRIES")]
+    retries: Option<u32>,
+
+    /// Restart a failed service, e.g. reconnect it, before retrying
+    #[clap(long, requires = "retries")]
+    restart: bool,
+
+    /// Relock automatically after the door was open for this long (in sec)
+    #[clap(long, env = "HOLD_OPEN_TIMEOUT")]
+    hold_open: Option<u64>,
+
+    /// How long to wait for the PIN of devices that require one (in sec)
+    #[clap(long, env = "PIN_TIMEOUT", default_value="30")]
+    pin_timeout: u64,
+
+    /// Alert approvers once the door was left unlocked for this long (in sec)
+    #[clap(long, env = "UNLOCKED_ALERT")]
+    unlocked_alert: Option<u64>,
+
+    /// Remind approvers at this interval while the door stays unlocked (in sec)
+    #[clap(long, env = "UNLOCKED_REMINDER", default_value="300")]
+    unlocked_reminder: u64,
+
+    /// Alert all approvers after this many alerts went unanswered
+    #[clap(long, env = "UNLOCKED_ESCALATE_AFTER")]
+    unlocked_escalate_after: Option<u32>,
 }
 
 #[derive(Clap, Debug, Clone)]
 #[clap()]
 struct Args {
     #[clap(flatten)]
+    #[cfg_attr(not(feature = "discord_base"), allow(dead_code))]
     discord_args: DiscordArgs,
 
     #[clap(flatten)]
+    #[cfg_attr(not(feature = "bluetooth"), allow(dead_code))]
     bluetooth_args: BluetoothArgs,
 
     #[clap(flatten)]
@@ -72,12 +151,56 @@ struct Args {
     /// Devices that are allowed authorize
     #[clap(short, long)]
     devices: PathBuf,
+
+    /// Name of the door, used in messages to approvers
+    #[clap(long, env = "DOOR")]
+    door: Option<String>,
+
+    /// Record every attempt in this JSON Lines file
+    #[clap(long, env = "AUDIT_LOG")]
+    audit_log: Option<PathBuf>,
+
+    /// Serve Prometheus metrics on this address (e.g. 127.0.0.1:9100)
+    #[cfg(feature = "metrics")]
+    #[clap(long, env = "METRICS_ADDR")]
+    metrics: Option<std::net::SocketAddr>,
+}
+
+/// Inspect the audit log
+#[derive(Clap, Debug, Clone)]
+#[clap(name = "doorman audit", bin_name = "doorman audit")]
+enum Audit {
+    /// Check that no entries were modified, removed or reordered
+    Verify {
+        /// The audit log to check
+        file: PathBuf,
+
+        /// Hash of the last entry reported by an earlier verification, detects removed entries at the end
+        #[clap(long)]
+        head: Option<String>,
+    },
+}
+
+/// Manage device PINs
+#[derive(Clap, Debug, Clone)]
+#[clap(name = "doorman pin", bin_name = "doorman pin")]
+enum Pin {
+    /// Hash a PIN read from stdin for the "pin" field of a device
+    Hash,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
 
+    // handled apart from the daemon arguments, which are mostly required
+    if std::env::args().nth(1).as_deref() == Some("audit") {
+        return audit(Audit::parse_from(std::env::args().skip(1)));
+    }
+    if std::env::args().nth(1).as_deref() == Some("pin") {
+        return pin(Pin::parse_from(std::env::args().skip(1)));
+    }
+
     let args = Args::parse();
 
     env_logger::Builder::from_default_env()
@@ -91,42 +214,204 @@ async fn main() -> anyhow::Result<()> {
         // .filter_module("doorman", log::LevelFilter::Debug)
         .init();
 
-    let mut registry = Registry::new();
-    registry.from_file(args.devices)?;
-
-    debug!("Registered Devices: {:?}", registry.list());
-
     cfg_if::cfg_if! {
         if #[cfg(feature="bluetooth")] {
-            let detector = BluetoothDetector::new(&registry).await?;
+            let mut registry: Registry<Address, BluetoothDevice> = Registry::new();
         } else {
-            let detector= simple::detector::Detector::new(&registry);
+            let mut registry: Registry<SimpleDevice, SimpleDevice> = Registry::new();
         }
     }
+    registry.from_file(args.devices)?;
+
+    debug!("Registered Devices: {:?}", registry.list());
+    let registry = Arc::new(registry);
+
+    let shutdown = CancellationToken::new();
+    // without signals the door could not be locked on exit, refuse to start
+    let terminate = signal(SignalKind::terminate())?;
+    tokio::spawn(shutdown_on_signal(terminate, shutdown.clone()));
+
+    #[cfg(feature = "metrics")]
+    let metrics = match args.metrics {
+        Some(addr) => {
+            let metrics = Metrics::new()?;
+            let server = metrics.clone().serve(addr, shutdown.clone())?;
+            tokio::spawn(async move {
+                if let Err(e) = server.await {
+                    error!("Metrics server failed: {}", e);
+                }
+            });
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    // devices can always be entered manually
+    let input = Input::new();
+    let detector = simple::detector::Detector::new(registry.clone(), input.clone());
+
+    #[cfg(feature = "bluetooth")]
+    let detector = {
+        let bluetooth = BluetoothDetector::new(registry.clone()).await?;
+        #[cfg(feature = "metrics")]
+        let bluetooth = bluetooth.with_metrics(metrics.clone());
+        let absence = Duration::from_secs(args.bluetooth_args.absence);
+        let bluetooth = Presence::new(bluetooth, absence);
+        tokio::spawn(log_presence(bluetooth.subscribe()));
+        Race::new(bluetooth, detector)
+    };
 
     cfg_if::cfg_if! {
         if #[cfg(feature="discord_base")] {
             let client = client::Client::new(args.discord_args.token, args.discord_args.user).await;
-            let client = client.run().await?;
-            let auth = DiscordAuth::new(&client);
-            let locker = DiscordLocker::new(&client);
+            let client = Arc::new(client.run().await?);
+
+            let grant = Duration::from_secs(args.discord_args.grant);
+            let named = args.door.clone();
+            let approver = |auth: DiscordAuth<_>| match named.as_ref() {
+                Some(door) => auth.with_grant(grant).at_door(door.clone()),
+                None => auth.with_grant(grant),
+            };
+            let mut approvers = vec![(approver(DiscordAuth::new(client.clone())), None)];
+            for user in args.discord_args.escalate {
+                let user = client.get_user(user).await?;
+                approvers.push((approver(DiscordAuth::for_user(client.clone(), user)), None));
+            }
+            let auth = Escalation::new(approvers, AuthenticateResult::Timeout);
+            let locker = DiscordLocker::new(client);
         }
         else {
-            let auth = authenticator::Authenticator::new();
-            let locker = Locker::new();
+            let auth = authenticator::Authenticator::new(input.clone());
+            let locker = Locker::new(input.clone());
         }
     }
+    let keypad = simple::keypad::Keypad::new(input);
 
-    let mut act = actuator::Actuator;
+    let act = actuator::Actuator;
 
+    let restart = args.manager_config.restart;
     let config = manager::Config {
         authorize_timeout: args.manager_config.timeout.map(Duration::from_secs),
-        reauthorize_timeout: Duration::from_secs(args.manager_config.cooldown)
+        reauthorize_timeout: Duration::from_secs(args.manager_config.cooldown),
+        max_reauthorize_timeout: Duration::from_secs(args.manager_config.max_cooldown),
+        denial_window: Duration::from_secs(args.manager_config.denial_window),
+        lockout_after: args.manager_config.lockout_after,
+        relock_timeout: args.manager_config.hold_open.map(Duration::from_secs),
+        recovery: args
+            .manager_config
+            .retries
+            .map(|attempts| {
+                let policy = RetryPolicy {
+                    attempts: Some(attempts),
+                    delay: Duration::from_secs(1),
+                    max_delay: Duration::from_secs(60),
+                };
+                RecoveryConfig::all(if restart {
+                    Recovery::Restart(policy)
+                } else {
+                    Recovery::Retry(policy)
+                })
+            })
+            .unwrap_or_default(),
+        dry_run: args.manager_config.dry_run,
+        report_auto_allowed: args.manager_config.report_trusted,
+        pin_timeout: Some(Duration::from_secs(args.manager_config.pin_timeout)),
+        unlocked_alert: args.manager_config.unlocked_alert.map(Duration::from_secs),
+        unlocked_reminder: Duration::from_secs(args.manager_config.unlocked_reminder),
+        unlocked_escalate_after: args.manager_config.unlocked_escalate_after,
     };
 
-    let mut manager = Manager::new(&detector, &auth, &mut act, &locker, config);
+    let door = args.door.unwrap_or_else(|| "main".to_string());
+    let mut supervisor = Supervisor::new(shutdown);
+    let door_detector = supervisor.door(door.clone());
+    supervisor.detector(detector, &[&door])?;
 
-    manager.daemon().await?;
+    let mut manager = Manager::new(door_detector, auth, act, locker, config);
+    manager.keypad(keypad);
+    tokio::spawn(clear_lockouts_on_signal(
+        signal(SignalKind::user_defined1())?,
+        manager.backoff(),
+    ));
+    if let Some(path) = args.audit_log {
+        manager.observe(AuditLog::open(path)?);
+    }
+    #[cfg(feature = "metrics")]
+    if let Some(metrics) = metrics {
+        metrics.track(manager.subscribe());
+        manager.observe(metrics);
+    }
+
+    supervisor.manage(door, manager);
+    supervisor.run().await?;
+
+    Ok(())
+}
+
+fn audit(command: Audit) -> anyhow::Result<()> {
+    match command {
+        Audit::Verify { file, head } => {
+            let log = BufReader::new(File::open(file)?);
+            let (entries, head) = audit::verify(log, head.as_deref())?;
+            println!("{} entries verified, head {}", entries, head);
+        }
+    }
+    Ok(())
+}
 
+fn pin(command: Pin) -> anyhow::Result<()> {
+    match command {
+        Pin::Hash => {
+            // read from stdin to keep the PIN out of the shell history
+            let mut pin = String::new();
+            io::stdin().lock().read_line(&mut pin)?;
+            let hash = access::hash_pin(pin.trim()).map_err(|e| anyhow::anyhow!("{}", e))?;
+            println!("{}", hash);
+        }
+    }
     Ok(())
 }
+
+/// Logs who arrives and leaves
+#[cfg(feature = "bluetooth")]
+async fn log_presence(mut events: tokio::sync::broadcast::Receiver<PresenceEvent<BluetoothDevice>>) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        match events.recv().await {
+            Ok(PresenceEvent::Arrived(device)) => info!("{} arrived", device),
+            Ok(PresenceEvent::Departed(device)) => info!("{} left", device),
+            Err(RecvError::Lagged(missed)) => debug!("Missed {} presence events", missed),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Clears all lockouts and cooldowns on SIGUSR1
+async fn clear_lockouts_on_signal<Device: Display + Hash + Eq + Clone>(
+    mut clear: Signal,
+    backoff: Backoff<Device>,
+) {
+    while clear.recv().await.is_some() {
+        for device in backoff.locked_out() {
+            info!("Lockout of {} cleared", device);
+        }
+        backoff.clear_all();
+        info!("Cleared all lockouts and cooldowns");
+    }
+}
+
+/// Cancels `shutdown` on SIGINT or SIGTERM
+async fn shutdown_on_signal(mut terminate: Signal, shutdown: CancellationToken) {
+    tokio::select! {
+        result = ctrl_c() => {
+            if let Err(e) = result {
+                error!("Could not listen for SIGINT, only SIGTERM shuts down: {}", e);
+                terminate.recv().await;
+            }
+        }
+        _ = terminate.recv() => {},
+    }
+
+    info!("Received signal, shutting down");
+    shutdown.cancel();
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::Config;
+
+/// Why a detected device is not authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blocked {
+    /// The device was denied recently, it is ignored for the remaining duration
+    Cooldown(Duration),
+    /// The device was denied too often and is ignored until the lockout is cleared
+    LockedOut,
+    /// The device was detected outside of its [`Schedule`](crate::access::Schedule)
+    OutsideSchedule,
+}
+
+#[derive(Debug)]
+struct Record {
+    denials: VecDeque<Instant>,
+    blocked_until: Option<Instant>,
+    locked_out: bool,
+}
+
+impl Record {
+    fn new() -> Self {
+        Self {
+            denials: VecDeque::new(),
+            blocked_until: None,
+            locked_out: false,
+        }
+    }
+}
+
+/// Per device cooldowns and lockouts after denials
+///
+/// Cloning yields a handle to the same records,
+/// e.g. to clear lockouts while the manager is running.
+#[derive(Debug)]
+pub struct Backoff<Device> {
+    records: Arc<Mutex<HashMap<Device, Record>>>,
+}
+
+impl<Device> Clone for Backoff<Device> {
+    fn clone(&self) -> Self {
+        Self {
+            records: self.records.clone(),
+        }
+    }
+}
+
+impl<Device: Hash + Eq + Clone> Backoff<Device> {
+    pub(crate) fn new() -> Self {
+        Self {
+            records: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Checks whether the device is currently blocked
+    pub fn blocked(&self, device: &Device) -> Option<Blocked> {
+        let records = self.records.lock().unwrap();
+        let record = records.get(device)?;
+
+        if record.locked_out {
+            return Some(Blocked::LockedOut);
+        }
+
+        let now = Instant::now();
+        record
+            .blocked_until
+            .filter(|until| *until > now)
+            .map(|until| Blocked::Cooldown(until - now))
+    }
+
+    /// Records a denial, the cooldown doubles with every denial within [`Config::denial_window`]
+    pub(crate) fn deny(&self, device: &Device, config: &Config) -> Blocked {
+        let mut records = self.records.lock().unwrap();
+        let record = records.entry(device.clone()).or_insert_with(Record::new);
+
+        let now = Instant::now();
+        record.denials.push_back(now);
+        while let Some(denial) = record.denials.front() {
+            if now.duration_since(*denial) < config.denial_window {
+                break;
+            }
+            record.denials.pop_front();
+        }
+
+        let denials = record.denials.len();
+        if matches!(config.lockout_after, Some(limit) if denials >= limit) {
+            record.locked_out = true;
+            return Blocked::LockedOut;
+        }
+
+        let cooldown = config
+            .reauthorize_timeout
+            .checked_mul(2u32.saturating_pow(denials as u32 - 1))
+            .unwrap_or(config.max_reauthorize_timeout)
+            .min(config.max_reauthorize_timeout);
+        record.blocked_until = Some(now + cooldown);
+        Blocked::Cooldown(cooldown)
+    }
+
+    /// An approver blocked the device, it is locked out right away
+    pub(crate) fn block(&self, device: &Device) {
+        let mut records = self.records.lock().unwrap();
+        let record = records.entry(device.clone()).or_insert_with(Record::new);
+
+        record.denials.push_back(Instant::now());
+        record.locked_out = true;
+    }
+
+    /// Nobody answered, the device is ignored for [`Config::reauthorize_timeout`]
+    pub(crate) fn timeout(&self, device: &Device, config: &Config) -> Blocked {
+        let mut records = self.records.lock().unwrap();
+        let record = records.entry(device.clone()).or_insert_with(Record::new);
+
+        record.blocked_until = Some(Instant::now() + config.reauthorize_timeout);
+        Blocked::Cooldown(config.reauthorize_timeout)
+    }
+
+    /// Forgets previous denials of an allowed device
+    pub(crate) fn allow(&self, device: &Device) {
+        self.records.lock().unwrap().remove(device);
+    }
+
+    /// Clears the lockout and cooldown of a device
+    ///
+    /// Returns `false` if nothing was recorded for the device.
+    pub fn clear(&self, device: &Device) -> bool {
+        self.records.lock().unwrap().remove(device).is_some()
+    }
+
+    /// Clears all lockouts and cooldowns
+    pub fn clear_all(&self) {
+        self.records.lock().unwrap().clear();
+    }
+
+    /// Devices that are currently locked out
+    pub fn locked_out(&self) -> Vec<Device> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, record)| record.locked_out)
+            .map(|(device, _)| device.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::advance;
+
+    use super::{Backoff, Blocked};
+    use crate::{manager::Config, testing::secs};
+
+    fn config() -> Config {
+        Config {
+            reauthorize_timeout: secs(30),
+            max_reauthorize_timeout: secs(100),
+            denial_window: secs(100),
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cooldown_doubles_up_to_maximum() {
+        let backoff = Backoff::new();
+        let config = config();
+
+        assert_eq!(backoff.deny(&"phone", &config), Blocked::Cooldown(secs(30)));
+        assert_eq!(backoff.deny(&"phone", &config), Blocked::Cooldown(secs(60)));
+        assert_eq!(
+            backoff.deny(&"phone", &config),
+            Blocked::Cooldown(secs(100))
+        );
+        assert_eq!(
+            backoff.blocked(&"phone"),
+            Some(Blocked::Cooldown(secs(100)))
+        );
+        assert_eq!(backoff.blocked(&"tablet"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn denials_outside_window_are_forgotten() {
+        let backoff = Backoff::new();
+        let config = Config {
+            lockout_after: Some(3),
+            ..config()
+        };
+
+        backoff.deny(&"phone", &config);
+        advance(secs(60)).await;
+        assert_eq!(backoff.deny(&"phone", &config), Blocked::Cooldown(secs(60)));
+
+        // the first denial left the window
+        advance(secs(50)).await;
+        assert_eq!(backoff.deny(&"phone", &config), Blocked::Cooldown(secs(60)));
+        assert_eq!(backoff.deny(&"phone", &config), Blocked::LockedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clear_lifts_lockout() {
+        let backoff = Backoff::new();
+        backoff.block(&"phone");
+        assert_eq!(backoff.locked_out(), vec!["phone"]);
+
+        assert!(backoff.clear(&"phone"));
+        assert_eq!(backoff.blocked(&"phone"), None);
+        assert!(!backoff.clear(&"phone"));
+    }
+}
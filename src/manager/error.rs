@@ -0,0 +1,174 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::DoorState;
+use crate::interfaces::services::ServiceError;
+
+/// The service a [`ManagerError`] originates from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Service {
+    Detector,
+    Authenticator,
+    Actuator,
+    Locker,
+    Keypad,
+}
+
+/// A failed service and its error
+#[derive(Error, Debug)]
+pub enum Failure<
+    DetectError: ServiceError,
+    AuthenticateError: ServiceError,
+    ActError: ServiceError,
+    LockError: ServiceError,
+> {
+    #[error("Detector experienced an Error: {0}")]
+    Detector(DetectError),
+    #[error("Authenticator experienced an Error: {0}")]
+    Authenticate(AuthenticateError),
+    #[error("Actuator experienced an Error: {0}")]
+    Actuate(ActError),
+    #[error("Locker experienced an Error: {0}")]
+    Lock(LockError),
+    #[error("Keypad experienced an Error: {0}")]
+    Keypad(Box<dyn ServiceError>),
+}
+
+impl<
+        DetectError: ServiceError,
+        AuthenticateError: ServiceError,
+        ActError: ServiceError,
+        LockError: ServiceError,
+    > Failure<DetectError, AuthenticateError, ActError, LockError>
+{
+    pub fn service(&self) -> Service {
+        match self {
+            Failure::Detector(_) => Service::Detector,
+            Failure::Authenticate(_) => Service::Authenticator,
+            Failure::Actuate(_) => Service::Actuator,
+            Failure::Lock(_) => Service::Locker,
+            Failure::Keypad(_) => Service::Keypad,
+        }
+    }
+}
+
+impl<
+        DetectError: ServiceError,
+        AuthenticateError: ServiceError,
+        ActError: ServiceError,
+        LockError: ServiceError,
+    > ServiceError for Failure<DetectError, AuthenticateError, ActError, LockError>
+{
+    fn is_fatal(&self) -> bool {
+        match self {
+            Failure::Detector(e) => e.is_fatal(),
+            Failure::Authenticate(e) => e.is_fatal(),
+            Failure::Actuate(e) => e.is_fatal(),
+            Failure::Lock(e) => e.is_fatal(),
+            Failure::Keypad(e) => e.is_fatal(),
+        }
+    }
+}
+
+/// A failed step of a [`Manager`](super::Manager) and what it was doing at the time
+#[derive(Debug)]
+pub struct ManagerError<
+    DetectError: ServiceError,
+    AuthenticateError: ServiceError,
+    ActError: ServiceError,
+    LockError: ServiceError,
+> {
+    pub failure: Failure<DetectError, AuthenticateError, ActError, LockError>,
+    /// The state the manager failed in
+    pub phase: DoorState,
+    /// The device that was handled, if any, by its [`Display`](std::fmt::Display) name
+    pub device: Option<String>,
+    /// Consecutive failed steps before this one
+    pub retries: u32,
+    /// Whether the configured [`Recovery`](super::Recovery) retries the step
+    pub recoverable: bool,
+}
+
+impl<
+        DetectError: ServiceError,
+        AuthenticateError: ServiceError,
+        ActError: ServiceError,
+        LockError: ServiceError,
+    > ManagerError<DetectError, AuthenticateError, ActError, LockError>
+{
+    /// Whether the failed step may succeed when retried
+    pub fn is_transient(&self) -> bool {
+        !self.is_fatal()
+    }
+
+    /// A serializable summary of the error
+    pub fn report(&self) -> ErrorReport {
+        ErrorReport {
+            service: self.failure.service(),
+            error: self.failure.to_string(),
+            phase: self.phase,
+            device: self.device.clone(),
+            retries: self.retries,
+            fatal: self.is_fatal(),
+            recoverable: self.recoverable,
+        }
+    }
+}
+
+impl<
+        DetectError: ServiceError,
+        AuthenticateError: ServiceError,
+        ActError: ServiceError,
+        LockError: ServiceError,
+    > fmt::Display for ManagerError<DetectError, AuthenticateError, ActError, LockError>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (state {}", self.failure, self.phase)?;
+        if let Some(device) = &self.device {
+            write!(f, ", device {}", device)?;
+        }
+        if self.retries > 0 {
+            write!(f, ", after {} retries", self.retries)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// the failure is part of the message rather than the source, services' errors need not be 'static
+impl<
+        DetectError: ServiceError,
+        AuthenticateError: ServiceError,
+        ActError: ServiceError,
+        LockError: ServiceError,
+    > std::error::Error for ManagerError<DetectError, AuthenticateError, ActError, LockError>
+{
+}
+
+impl<
+        DetectError: ServiceError,
+        AuthenticateError: ServiceError,
+        ActError: ServiceError,
+        LockError: ServiceError,
+    > ServiceError for ManagerError<DetectError, AuthenticateError, ActError, LockError>
+{
+    fn is_fatal(&self) -> bool {
+        self.failure.is_fatal()
+    }
+}
+
+/// Serializable form of a [`ManagerError`], e.g. for the audit log or remote APIs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    pub service: Service,
+    /// The error message of the service
+    pub error: String,
+    pub phase: DoorState,
+    pub device: Option<String>,
+    pub retries: u32,
+    /// Whether the service reported the error as fatal
+    pub fatal: bool,
+    pub recoverable: bool,
+}
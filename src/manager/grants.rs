@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// Stands in for grants that do not fit into an [`Instant`]
+const FOREVER: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Devices approvers allowed for a while, they are let in without asking
+///
+/// Cloning yields a handle to the same grants,
+/// e.g. to revoke a grant while the manager is running.
+#[derive(Debug)]
+pub struct Grants<Device> {
+    until: Arc<Mutex<HashMap<Device, Instant>>>,
+}
+
+impl<Device> Clone for Grants<Device> {
+    fn clone(&self) -> Self {
+        Self {
+            until: self.until.clone(),
+        }
+    }
+}
+
+impl<Device: Hash + Eq + Clone> Grants<Device> {
+    pub(crate) fn new() -> Self {
+        Self {
+            until: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The remaining duration of the device's grant, if any
+    pub fn granted(&self, device: &Device) -> Option<Duration> {
+        let mut until = self.until.lock().unwrap();
+        let now = Instant::now();
+
+        match until.get(device) {
+            Some(expires) if *expires > now => Some(*expires - now),
+            Some(_) => {
+                until.remove(device);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Allows the device without asking for `duration`, replacing an earlier grant
+    pub(crate) fn grant(&self, device: &Device, duration: Duration) {
+        // durations too large to represent never expire in practice
+        let expires = Instant::now()
+            .checked_add(duration)
+            .unwrap_or_else(|| Instant::now() + FOREVER);
+        self.until.lock().unwrap().insert(device.clone(), expires);
+    }
+
+    /// Revokes the grant of a device
+    ///
+    /// Returns `false` if the device had no grant.
+    pub fn revoke(&self, device: &Device) -> bool {
+        self.until.lock().unwrap().remove(device).is_some()
+    }
+
+    /// Revokes all grants
+    pub fn revoke_all(&self) {
+        self.until.lock().unwrap().clear();
+    }
+}
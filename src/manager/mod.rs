@@ -0,0 +1,725 @@
+use std::{fmt::Display, hash::Hash, time::Duration};
+
+use crate::access::{Outside, Restricted, Trust};
+use crate::interfaces::services::{
+    Actuator, Authenticate, AuthenticateResult, Detector, HoldOpen, Keypad, Locker, Notice,
+    ServiceError, Withdrawal,
+};
+use async_trait::async_trait;
+use chrono::Local;
+use futures::future;
+use log::{debug, info, warn};
+use tokio::{
+    sync::watch,
+    time::{sleep, sleep_until, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+mod backoff;
+mod error;
+mod grants;
+mod observer;
+mod recovery;
+mod state;
+#[cfg(test)]
+mod tests;
+
+pub use backoff::{Backoff, Blocked};
+pub use error::{ErrorReport, Failure, ManagerError, Service};
+pub use grants::Grants;
+pub use observer::{Actuation, Attempt, Event, Observer};
+pub use recovery::{Recovery, RecoveryConfig, RetryPolicy};
+pub use state::DoorState;
+
+type ServicesError<Detect, Auth, Act, Lock> = ManagerError<
+    <Detect as Detector>::DetectorError,
+    <Auth as Authenticate>::AuthenticateError,
+    <Act as Actuator>::ActuatorError,
+    <Lock as Locker>::LockerError,
+>;
+
+type ServicesFailure<Detect, Auth, Act, Lock> = Failure<
+    <Detect as Detector>::DetectorError,
+    <Auth as Authenticate>::AuthenticateError,
+    <Act as Actuator>::ActuatorError,
+    <Lock as Locker>::LockerError,
+>;
+
+type ManagerResult<T, Detect, Auth, Act, Lock> = Result<T, ServicesError<Detect, Auth, Act, Lock>>;
+
+/// A [`Keypad`] with its error boxed, so that the manager does not depend on its type
+#[async_trait]
+trait DynKeypad: Send + Sync {
+    async fn read_pin(&self, timeout: Option<Duration>) -> Result<Option<String>, Box<dyn ServiceError>>;
+    async fn restart(&self) -> Result<(), Box<dyn ServiceError>>;
+}
+
+#[async_trait]
+impl<K> DynKeypad for K
+where
+    K: Keypad + Send + Sync,
+    K::KeypadError: 'static,
+{
+    async fn read_pin(&self, timeout: Option<Duration>) -> Result<Option<String>, Box<dyn ServiceError>> {
+        Keypad::read_pin(self, timeout)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn ServiceError>)
+    }
+
+    async fn restart(&self) -> Result<(), Box<dyn ServiceError>> {
+        Keypad::restart(self)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn ServiceError>)
+    }
+}
+
+pub struct Config {
+    pub authorize_timeout: Option<Duration>,
+    /// Cooldown of a device after a denial, doubled with every further denial
+    pub reauthorize_timeout: Duration,
+    /// Upper bound of the cooldown after repeated denials
+    pub max_reauthorize_timeout: Duration,
+    /// Denials older than this are forgotten
+    pub denial_window: Duration,
+    /// Lock a device out after this many denials within [`Config::denial_window`]
+    pub lockout_after: Option<usize>,
+    /// Relock automatically once the door was held open for this long
+    pub relock_timeout: Option<Duration>,
+    /// How the daemon recovers from service errors
+    pub recovery: RecoveryConfig,
+    /// Run the full flow without ever opening the door
+    pub dry_run: bool,
+    /// Inform approvers after the door was opened for a trusted device
+    pub report_auto_allowed: bool,
+    /// How long to wait for the PIN of a device that requires one
+    pub pin_timeout: Option<Duration>,
+    /// Alert approvers once the door was left unlocked for this long
+    pub unlocked_alert: Option<Duration>,
+    /// Remind approvers at this interval while the door stays unlocked
+    pub unlocked_reminder: Duration,
+    /// Escalate alerts after this many went unanswered
+    pub unlocked_escalate_after: Option<u32>,
+}
+
+/// The defaults of the command line
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            authorize_timeout: None,
+            reauthorize_timeout: Duration::from_secs(30),
+            max_reauthorize_timeout: Duration::from_secs(60 * 60),
+            denial_window: Duration::from_secs(24 * 60 * 60),
+            lockout_after: None,
+            relock_timeout: None,
+            recovery: RecoveryConfig::default(),
+            dry_run: false,
+            report_auto_allowed: false,
+            pin_timeout: Some(Duration::from_secs(30)),
+            unlocked_alert: None,
+            unlocked_reminder: Duration::from_secs(5 * 60),
+            unlocked_escalate_after: None,
+        }
+    }
+}
+
+/// Drives a single door
+///
+/// The manager owns its services, services shared with other parts of the program
+/// (e.g. a second door) can be passed as [`Arc`](std::sync::Arc)s.
+/// As long as the services are, the manager is `Send + 'static` and can be spawned as a task.
+pub struct Manager<Detect, Auth, Act, Lock>
+where
+    Detect: Detector,
+    Auth: Authenticate<Device = Detect::Device>,
+    Act: Actuator,
+    Lock: Locker,
+{
+    locker: Lock,
+    detector: Detect,
+    auth: Auth,
+    act: Act,
+    config: Config,
+    state: watch::Sender<DoorState>,
+    state_receiver: watch::Receiver<DoorState>,
+    observers: Vec<Box<dyn Observer<Detect::Device>>>,
+    /// Reads the PIN of devices that require one
+    keypad: Option<Box<dyn DynKeypad>>,
+    backoff: Backoff<Detect::Device>,
+    grants: Grants<Detect::Device>,
+    /// Consecutive failed steps
+    failures: u32,
+}
+
+impl<Detect, Auth, Act, Lock> Manager<Detect, Auth, Act, Lock>
+where
+    Detect: Detector + Sync,
+    Detect::Device: Hash + Eq + Clone + Display + Restricted + Sync,
+    Auth: Authenticate<Device = Detect::Device> + Sync,
+    Act: Actuator,
+    Lock: Locker + Sync,
+{
+    pub fn new(detector: Detect, auth: Auth, act: Act, locker: Lock, config: Config) -> Self {
+        // the daemon starts by waiting for the door to be locked
+        let (state, state_receiver) = watch::channel(DoorState::Open);
+
+        Self {
+            locker,
+            detector,
+            auth,
+            act,
+            config,
+            state,
+            state_receiver,
+            observers: Vec::new(),
+            keypad: None,
+            backoff: Backoff::new(),
+            grants: Grants::new(),
+            failures: 0,
+        }
+    }
+
+    /// Handle to the cooldowns and lockouts of denied devices
+    pub fn backoff(&self) -> Backoff<Detect::Device> {
+        self.backoff.clone()
+    }
+
+    /// Handle to the devices approvers allowed for a while
+    pub fn grants(&self) -> Grants<Detect::Device> {
+        self.grants.clone()
+    }
+
+    /// Register an observer that is notified about every significant step
+    pub fn observe(&mut self, observer: impl Observer<Detect::Device> + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Use a keypad for devices that require a PIN, without one they are always denied
+    pub fn keypad(&mut self, keypad: impl Keypad + Send + Sync + 'static) {
+        self.keypad = Some(Box::new(keypad));
+    }
+
+    fn notify(&self, event: Event<'_, Detect::Device>) {
+        for observer in self.observers.iter() {
+            observer.notify(&event);
+        }
+    }
+
+    /// The state the manager is currently in
+    pub fn state(&self) -> DoorState {
+        *self.state_receiver.borrow()
+    }
+
+    /// Subscribe to state transitions
+    pub fn subscribe(&self) -> watch::Receiver<DoorState> {
+        self.state_receiver.clone()
+    }
+
+    fn transition(&self, next: DoorState) {
+        debug!("Transition {} -> {}", self.state(), next);
+        // cannot fail, `self` holds a receiver
+        let _ = self.state.send(next);
+    }
+
+    /// Moves into [`DoorState::Error`] and passes the error on, with what the manager was doing
+    fn fail(
+        &self,
+        failure: ServicesFailure<Detect, Auth, Act, Lock>,
+        device: Option<&Detect::Device>,
+    ) -> ServicesError<Detect, Auth, Act, Lock> {
+        let recoverable = !failure.is_fatal()
+            && match self.recovery(&failure).0 {
+                Recovery::Fatal => false,
+                Recovery::Retry(policy) | Recovery::Restart(policy) => {
+                    policy.delay(self.failures + 1).is_some()
+                }
+            };
+        let error = ManagerError {
+            failure,
+            phase: self.state(),
+            device: device.map(ToString::to_string),
+            retries: self.failures,
+            recoverable,
+        };
+
+        self.notify(Event::ServiceError(&error.report()));
+        self.transition(DoorState::Error);
+        error
+    }
+
+    /// A single attempt: detect a device, authenticate it and open the door if allowed
+    ///
+    /// Ends in [`DoorState::Open`] if the door was opened, [`DoorState::Locked`] otherwise.
+    pub async fn run(&mut self) -> ManagerResult<AuthenticateResult, Detect, Auth, Act, Lock> {
+        info!("Waiting for device...");
+        self.transition(DoorState::Detecting);
+
+        let device = loop {
+            let device = self
+                .detector
+                .wait_for_device()
+                .await
+                .map_err(|e| self.fail(Failure::Detector(e), None))?;
+
+            if let Some(blocked) = self.backoff.blocked(&device) {
+                debug!("Ignoring device {:?} ({:?})", device, blocked);
+                self.notify(Event::Ignored(&device, blocked));
+                continue;
+            }
+
+            match device.access().schedule.as_ref() {
+                Some(schedule) if !schedule.allows(Local::now().naive_local()) => {
+                    match schedule.outside {
+                        Outside::Ignore => {
+                            debug!("Ignoring device {:?} outside its schedule", device);
+                            self.notify(Event::Ignored(&device, Blocked::OutsideSchedule));
+                        }
+                        Outside::Deny => {
+                            info!("Device {:?} detected outside its schedule, denying", device);
+                            self.notify(Event::DeviceDetected(&device));
+                            self.notify(Event::Denied(&device));
+                            self.notify(Event::Attempt(Attempt {
+                                device: &device,
+                                detector: self.detector.source(&device),
+                                approver: Some("schedule".to_string()),
+                                decision: AuthenticateResult::Deny,
+                                latency: Duration::ZERO,
+                                actuation: Actuation::NotRequired,
+                            }));
+                            // not counted as a denial, the approvers never saw it
+                            self.backoff.timeout(&device, &self.config);
+                            self.transition(DoorState::Locked);
+                            return Ok(AuthenticateResult::Deny);
+                        }
+                    }
+                }
+                _ => break device,
+            }
+        };
+
+        self.notify(Event::DeviceDetected(&device));
+        let detector = self.detector.source(&device);
+        let detected_at = Instant::now();
+
+        let second_factor = match device.access().pin {
+            None => None,
+            Some(_) => {
+                info!("Device {:?} requires a PIN", device);
+                self.transition(DoorState::Authenticating);
+                self.notify(Event::PinRequested(&device));
+
+                let verified = match self.keypad.as_ref() {
+                    Some(keypad) => keypad
+                        .read_pin(self.config.pin_timeout)
+                        .await
+                        .map_err(|e| self.fail(Failure::Keypad(e), Some(&device)))?
+                        .map(|pin| device.access().verify_pin(&pin)),
+                    None => {
+                        warn!("No keypad to enter the PIN of device {:?}", device);
+                        Some(false)
+                    }
+                };
+
+                match verified {
+                    Some(true) => None,
+                    Some(false) => {
+                        info!("Wrong PIN for device {:?}", device);
+                        self.notify(Event::PinRejected(&device));
+                        Some(AuthenticateResult::Deny)
+                    }
+                    None => {
+                        info!("No PIN entered for device {:?}", device);
+                        Some(AuthenticateResult::Timeout)
+                    }
+                }
+            }
+        };
+
+        // a visitor who left before approvers decided, nobody is there to let in
+        let mut left = false;
+
+        // only devices that passed the second factor reach the approvers or are allowed
+        let (authentication, approver) = match (second_factor, device.access().trust) {
+            (Some(result), _) => (result, Some("pin".to_string())),
+            (None, Trust::Allow) => {
+                info!("Trusted device {:?} detected, allowing", device);
+                self.notify(Event::AutoAllowed(&device));
+                (AuthenticateResult::Allow, Some("trust".to_string()))
+            }
+            (None, Trust::Deny) => {
+                info!("Untrusted device {:?} detected, denying", device);
+                self.notify(Event::AutoDenied(&device));
+                (AuthenticateResult::Deny, Some("trust".to_string()))
+            }
+            (None, Trust::Approve) if self.grants.granted(&device).is_some() => {
+                info!("Device {:?} was granted access, allowing", device);
+                self.notify(Event::AutoAllowed(&device));
+                (AuthenticateResult::Allow, Some("grant".to_string()))
+            }
+            (None, Trust::Approve) => {
+                info!("Device detected attempting authentication...");
+                self.transition(DoorState::Authenticating);
+                self.notify(Event::AuthenticationRequested(&device));
+
+                let authentication = tokio::select! {
+                    authentication = self.auth.authenticate(&device, self.config.authorize_timeout) => {
+                        Some(authentication)
+                    }
+                    _ = self.detector.wait_for_departure(&device) => None,
+                };
+
+                match authentication {
+                    Some(authentication) => {
+                        let authentication = authentication
+                            .map_err(|e| self.fail(Failure::Authenticate(e), Some(&device)))?;
+                        let approver = match authentication {
+                            AuthenticateResult::Timeout => None,
+                            _ => self.auth.approver(),
+                        };
+                        (authentication, approver)
+                    }
+                    None => {
+                        info!("Device {:?} left during authentication", device);
+                        left = true;
+                        if let Err(e) = self.auth.withdraw(Withdrawal::Left).await {
+                            warn!("Could not withdraw authentication request: {}", e);
+                        }
+                        (AuthenticateResult::Timeout, Some("presence".to_string()))
+                    }
+                }
+            }
+        };
+
+        let latency = detected_at.elapsed();
+        let attempt = |actuation| Attempt {
+            device: &device,
+            detector: detector.clone(),
+            approver: approver.clone(),
+            decision: authentication.clone(),
+            latency,
+            actuation,
+        };
+
+        let actuation = match &authentication {
+            AuthenticateResult::Allow | AuthenticateResult::AllowFor(_) => {
+                self.backoff.allow(&device);
+                self.notify(Event::Allowed(&device));
+                if let AuthenticateResult::AllowFor(duration) = authentication {
+                    info!("Device {:?} allowed without asking for {:?}", device, duration);
+                    self.grants.grant(&device, duration);
+                    self.notify(Event::Granted(&device, duration));
+                }
+                self.transition(DoorState::Opening);
+                if self.config.dry_run {
+                    info!("Dry run: would have opened the door for {:?}", device);
+                    self.notify(Event::DryRun(&device));
+                    if let Err(e) = self.auth.inform(&Notice::WouldOpen(&device)).await {
+                        warn!("Could not inform approvers: {}", e);
+                    }
+                    Actuation::DryRun
+                } else {
+                    if let Err(e) = self.act.open() {
+                        self.notify(Event::Attempt(attempt(Actuation::Failed(e.to_string()))));
+                        return Err(self.fail(Failure::Actuate(e), Some(&device)));
+                    }
+                    self.notify(Event::ActuatorFired);
+                    if self.config.report_auto_allowed && device.access().trust == Trust::Allow {
+                        if let Err(e) = self.auth.inform(&Notice::AutoAllowed(&device)).await {
+                            warn!("Could not inform approvers: {}", e);
+                        }
+                    }
+                    Actuation::Fired
+                }
+            }
+            AuthenticateResult::Deny | AuthenticateResult::DenyWithReason(_) => {
+                match &authentication {
+                    AuthenticateResult::DenyWithReason(reason) => {
+                        info!("Access with device {:?} denied: {}", device, reason)
+                    }
+                    _ => info!("Access with device {:?} denied", device),
+                }
+                self.notify(Event::Denied(&device));
+                if let Blocked::LockedOut = self.backoff.deny(&device, &self.config) {
+                    info!("Device {:?} locked out", device);
+                    self.notify(Event::LockedOut(&device));
+                }
+                Actuation::NotRequired
+            }
+            AuthenticateResult::DenyAndBlock => {
+                info!("Access with device {:?} denied, blocking it", device);
+                self.notify(Event::Denied(&device));
+                // a blocked device stays out even if it holds a grant
+                self.grants.revoke(&device);
+                self.backoff.block(&device);
+                self.notify(Event::LockedOut(&device));
+                Actuation::NotRequired
+            }
+            // asked again on its next arrival, unlike a device that timed out
+            AuthenticateResult::Timeout if left => {
+                self.notify(Event::Left(&device));
+                Actuation::NotRequired
+            }
+            AuthenticateResult::Timeout => {
+                info!("Authentication of device {:?} timed out", device);
+                self.notify(Event::TimedOut(&device));
+                self.backoff.timeout(&device, &self.config);
+                Actuation::NotRequired
+            }
+        };
+
+        // a dry run leaves the door locked, there is nothing to relock
+        let opened = actuation == Actuation::Fired;
+        self.notify(Event::Attempt(attempt(actuation)));
+        if opened {
+            self.transition(DoorState::Open);
+        } else {
+            self.transition(DoorState::Locked);
+        }
+        Ok(authentication)
+    }
+
+    /// Waits for the door to be locked and confirms it
+    ///
+    /// If configured, the door is relocked automatically after [`Config::relock_timeout`],
+    /// the locker may extend or cancel the relock timer.
+    /// Approvers are alerted while the door stays unlocked, see [`Config::unlocked_alert`],
+    /// an acknowledgement of an approver or a request of the locker restarts the alerts.
+    async fn lock(&mut self) -> ManagerResult<(), Detect, Auth, Act, Lock> {
+        let mut wait_for_lock = self.locker.wait_for_lock();
+        let mut relock_at = self.config.relock_timeout.map(|t| Instant::now() + t);
+        // only asked again once a request was made, the locker may prompt for every request
+        let mut hold_open = relock_at.map(|_| self.locker.hold_open());
+        let unlocked_at = Instant::now();
+        let mut alert_at = self.config.unlocked_alert.map(|t| unlocked_at + t);
+        let mut acknowledged = None;
+        let mut unanswered = 0;
+
+        loop {
+            let relock = async {
+                match relock_at {
+                    Some(at) => sleep_until(at).await,
+                    None => future::pending().await,
+                }
+            };
+            let request = async {
+                match hold_open.as_mut() {
+                    Some(request) => request.await,
+                    None => future::pending().await,
+                }
+            };
+            let alert = async {
+                match alert_at {
+                    Some(at) => sleep_until(at).await,
+                    None => future::pending().await,
+                }
+            };
+            let acknowledgement = async {
+                match acknowledged.as_mut() {
+                    Some(acknowledgement) => acknowledgement.await,
+                    None => future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                locked = &mut wait_for_lock => {
+                    locked
+                        .map_err(|e| self.fail(Failure::Lock(e), None))?;
+                    self.notify(Event::LockRequested);
+                    break;
+                }
+                _ = relock => {
+                    info!("Door was held open for too long, relocking");
+                    self.notify(Event::HoldOpenExpired);
+                    break;
+                }
+                _ = alert => {
+                    let open_for = unlocked_at.elapsed();
+                    let escalated = matches!(
+                        self.config.unlocked_escalate_after,
+                        Some(after) if unanswered >= after
+                    );
+                    warn!("Door left unlocked for {:?}, alerting approvers", open_for);
+                    self.notify(Event::LeftUnlocked(open_for, escalated));
+                    if let Err(e) = self.auth.inform(&Notice::LeftUnlocked(open_for, escalated)).await {
+                        warn!("Could not alert approvers: {}", e);
+                    }
+                    unanswered += 1;
+                    alert_at = Some(Instant::now() + self.config.unlocked_reminder);
+                    acknowledged = Some(self.auth.acknowledged());
+                }
+                result = acknowledgement => {
+                    acknowledged = None;
+                    match result {
+                        Ok(()) => {
+                            info!("Unlocked door acknowledged");
+                            unanswered = 0;
+                            alert_at = self.config.unlocked_alert.map(|t| Instant::now() + t);
+                        }
+                        Err(e) => warn!("Could not wait for approvers to acknowledge: {}", e),
+                    }
+                }
+                request = request => {
+                    unanswered = 0;
+                    alert_at = self.config.unlocked_alert.map(|t| Instant::now() + t);
+                    match request.map_err(|e| self.fail(Failure::Lock(e), None))? {
+                        HoldOpen::Extend => {
+                            info!("Relock timer extended");
+                            relock_at = self.config.relock_timeout.map(|t| Instant::now() + t);
+                        }
+                        HoldOpen::Cancel => {
+                            info!("Relock timer cancelled");
+                            relock_at = None;
+                        }
+                    }
+                    hold_open = relock_at.map(|_| self.locker.hold_open());
+                }
+            }
+        }
+
+        self.locker
+            .confirm_lock()
+            .await
+            .map_err(|e| self.fail(Failure::Lock(e), None))?;
+        self.notify(Event::LockConfirmed);
+
+        self.transition(DoorState::Locked);
+        Ok(())
+    }
+
+    /// Runs until `shutdown` is cancelled or a step failed for good
+    ///
+    /// The door is locked before returning either way.
+    pub async fn daemon(
+        &mut self,
+        shutdown: CancellationToken,
+    ) -> ManagerResult<(), Detect, Auth, Act, Lock> {
+        loop {
+            let step = async {
+                let result = match self.state() {
+                    // an interrupted opening might have left the door open
+                    DoorState::Open | DoorState::Opening | DoorState::Error => self.lock().await,
+                    DoorState::Locked | DoorState::Detecting | DoorState::Authenticating => {
+                        self.run().await.map(|_| ())
+                    }
+                };
+
+                match result {
+                    Ok(()) => {
+                        self.failures = 0;
+                        Ok(())
+                    }
+                    Err(error) => self.recover(error).await,
+                }
+            };
+
+            let result = tokio::select! {
+                result = step => result,
+                _ = shutdown.cancelled() => break,
+            };
+
+            if let Err(error) = result {
+                // the failed step may have left the door open or a request pending
+                if let Err(e) = self.shutdown(error.phase).await {
+                    warn!("Could not shut down after failure: {}", e);
+                }
+                return Err(error);
+            }
+        }
+
+        self.shutdown(self.state()).await
+    }
+
+    /// The configured [`Recovery`] of a failed service and the state to resume in
+    fn recovery(&self, failure: &ServicesFailure<Detect, Auth, Act, Lock>) -> (Recovery, DoorState) {
+        // locker errors leave the door open, all others locked
+        match failure {
+            Failure::Detector(_) => (self.config.recovery.detector, DoorState::Locked),
+            Failure::Authenticate(_) => (self.config.recovery.authenticator, DoorState::Locked),
+            Failure::Actuate(_) => (self.config.recovery.actuator, DoorState::Locked),
+            Failure::Lock(_) => (self.config.recovery.locker, DoorState::Open),
+            Failure::Keypad(_) => (self.config.recovery.keypad, DoorState::Locked),
+        }
+    }
+
+    /// Applies the configured [`Recovery`] to a failed step
+    ///
+    /// Returns the error if it cannot be recovered from,
+    /// otherwise prepares retrying the failed step.
+    async fn recover(
+        &mut self,
+        error: ServicesError<Detect, Auth, Act, Lock>,
+    ) -> ManagerResult<(), Detect, Auth, Act, Lock> {
+        let (recovery, resume) = self.recovery(&error.failure);
+
+        if error.is_fatal() {
+            return Err(error);
+        }
+
+        let policy = match recovery {
+            Recovery::Fatal => return Err(error),
+            Recovery::Retry(policy) | Recovery::Restart(policy) => policy,
+        };
+
+        self.failures += 1;
+        let delay = match policy.delay(self.failures) {
+            Some(delay) => delay,
+            None => {
+                warn!("Giving up after {} failures", self.failures);
+                return Err(error);
+            }
+        };
+
+        warn!(
+            "{}, retrying in {:?} (attempt {})",
+            error, delay, self.failures
+        );
+        sleep(delay).await;
+
+        if let Recovery::Restart(_) = recovery {
+            info!("Restarting failed service");
+            // a failed restart shows in the retried step
+            let restarted = match error.failure {
+                Failure::Detector(_) => self.detector.restart().await.map_err(Failure::Detector),
+                Failure::Authenticate(_) => {
+                    self.auth.restart().await.map_err(Failure::Authenticate)
+                }
+                Failure::Actuate(_) => self.act.restart().map_err(Failure::Actuate),
+                Failure::Lock(_) => self.locker.restart().await.map_err(Failure::Lock),
+                Failure::Keypad(_) => match self.keypad.as_ref() {
+                    Some(keypad) => keypad.restart().await.map_err(Failure::Keypad),
+                    None => Ok(()),
+                },
+            };
+            if let Err(e) = restarted {
+                warn!("Restart failed: {}", e);
+            }
+        }
+
+        self.transition(resume);
+        Ok(())
+    }
+
+    /// Cleans up after the step the daemon was interrupted or failed in
+    async fn shutdown(&mut self, state: DoorState) -> ManagerResult<(), Detect, Auth, Act, Lock> {
+        info!("Shutting down in state {}", state);
+
+        match state {
+            DoorState::Authenticating => {
+                if let Err(e) = self.auth.withdraw(Withdrawal::Shutdown).await {
+                    warn!("Could not withdraw authentication request: {}", e);
+                }
+            }
+            DoorState::Open | DoorState::Opening | DoorState::Error => {
+                info!("Locking door before exit");
+                self.locker
+                    .confirm_lock()
+                    .await
+                    .map_err(|e| self.fail(Failure::Lock(e), None))?;
+                self.notify(Event::LockConfirmed);
+            }
+            DoorState::Locked | DoorState::Detecting => {}
+        }
+
+        self.transition(DoorState::Locked);
+        Ok(())
+    }
+}
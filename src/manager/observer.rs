@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::{Blocked, ErrorReport};
+use crate::interfaces::services::AuthenticateResult;
+
+/// What happened to the actuator during an attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Actuation {
+    /// Access was not granted
+    NotRequired,
+    /// Access was granted during a dry run
+    DryRun,
+    Fired,
+    Failed(String),
+}
+
+/// Summary of a single [`Manager::run`](super::Manager::run)
+#[derive(Debug)]
+pub struct Attempt<'a, Device> {
+    pub device: &'a Device,
+    /// See [`Detector::source`](crate::interfaces::services::Detector::source)
+    pub detector: String,
+    /// Who decided, `None` if nobody did
+    pub approver: Option<String>,
+    pub decision: AuthenticateResult,
+    /// Time from detection to decision
+    pub latency: Duration,
+    pub actuation: Actuation,
+}
+
+/// Significant steps of a [`Manager`](super::Manager)
+#[derive(Debug)]
+pub enum Event<'a, Device> {
+    /// The detector reported a registered device
+    DeviceDetected(&'a Device),
+    /// A detected device was ignored, e.g. because of previous denials
+    Ignored(&'a Device, Blocked),
+    /// The device requires a PIN, waiting for it to be entered
+    PinRequested(&'a Device),
+    /// A wrong PIN was entered, access is denied without asking an approver
+    PinRejected(&'a Device),
+    /// A trusted device or one that was granted access was allowed without asking an approver
+    AutoAllowed(&'a Device),
+    /// A device that is never trusted was denied without asking an approver
+    AutoDenied(&'a Device),
+    /// An approver is asked to authenticate the device
+    AuthenticationRequested(&'a Device),
+    /// Access was granted
+    Allowed(&'a Device),
+    /// The device is allowed without asking for the given duration
+    Granted(&'a Device, Duration),
+    /// Access was denied
+    Denied(&'a Device),
+    /// No decision was made in time
+    TimedOut(&'a Device),
+    /// The device went out of range before a decision was made, the request was withdrawn
+    Left(&'a Device),
+    /// The device was denied too often and is locked out
+    LockedOut(&'a Device),
+    /// The door was opened
+    ActuatorFired,
+    /// Dry run: access was granted but the door was not opened
+    DryRun(&'a Device),
+    /// The locker reported that the door should be locked
+    LockRequested,
+    /// The door was held open for too long and is relocked automatically
+    HoldOpenExpired,
+    /// The door has been unlocked for the given duration and approvers were alerted,
+    /// escalated once earlier alerts were not responded to
+    LeftUnlocked(Duration, bool),
+    /// The lock was confirmed to the user
+    LockConfirmed,
+    /// A service failed
+    ServiceError(&'a ErrorReport),
+    /// An attempt ended, emitted once per detected device that was not ignored
+    Attempt(Attempt<'a, Device>),
+}
+
+/// Hooks into the lifecycle of a [`Manager`](super::Manager)
+///
+/// Observers are called synchronously from the manager loop,
+/// long running work should be moved elsewhere (e.g. a channel or spawned task).
+pub trait Observer<Device>: Send + Sync {
+    fn notify(&self, event: &Event<'_, Device>);
+}
+
+impl<Device, F> Observer<Device> for F
+where
+    F: Fn(&Event<'_, Device>) + Send + Sync,
+{
+    fn notify(&self, event: &Event<'_, Device>) {
+        self(event)
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+/// How often and how fast a failed step is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Give up after this many consecutive failures, `None` retries forever
+    pub attempts: Option<u32>,
+    /// Delay before the first retry, doubled for every further retry
+    pub delay: Duration,
+    /// Upper bound of the delay between retries
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, `None` once all attempts are used up
+    pub(crate) fn delay(&self, attempt: u32) -> Option<Duration> {
+        if matches!(self.attempts, Some(attempts) if attempt > attempts) {
+            return None;
+        }
+
+        let delay = self
+            .delay
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        Some(delay)
+    }
+}
+
+/// What the daemon does when a service fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Stop the daemon with the error
+    Fatal,
+    /// Retry the failed step
+    Retry(RetryPolicy),
+    /// Restart the service before retrying the failed step
+    Restart(RetryPolicy),
+}
+
+/// [`Recovery`] per service
+///
+/// Errors a service marks as [fatal](crate::interfaces::services::ServiceError::is_fatal)
+/// always stop the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryConfig {
+    pub detector: Recovery,
+    pub authenticator: Recovery,
+    pub actuator: Recovery,
+    pub locker: Recovery,
+    pub keypad: Recovery,
+}
+
+impl RecoveryConfig {
+    /// Recover from errors of all services alike
+    pub fn all(recovery: Recovery) -> Self {
+        Self {
+            detector: recovery,
+            authenticator: recovery,
+            actuator: recovery,
+            locker: recovery,
+            keypad: recovery,
+        }
+    }
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self::all(Recovery::Fatal)
+    }
+}
@@ -0,0 +1,23 @@
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+/// The phases a [`Manager`](super::Manager) moves through.
+///
+/// The current state is published through a [`tokio::sync::watch`] channel,
+/// see [`Manager::subscribe`](super::Manager::subscribe).
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DoorState {
+    /// The door is locked and no detection is running (e.g. during a cooldown)
+    Locked,
+    /// Waiting for a registered device to show up
+    Detecting,
+    /// Waiting for an approver to answer
+    Authenticating,
+    /// Access was granted, the actuator is being fired
+    Opening,
+    /// The door is open, waiting for the locker
+    Open,
+    /// A service failed, the door has to be locked before detecting again
+    Error,
+}
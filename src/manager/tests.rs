@@ -0,0 +1,469 @@
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+use tokio_util::sync::CancellationToken;
+
+use super::{Config, Manager};
+use crate::{
+    access::{Access, Trust},
+    combinators::{
+        authenticator::{Approvers, Escalation},
+        detector::{Presence, Race},
+    },
+    interfaces::services::{Authenticate, AuthenticateResult, Detector, HoldOpen, Withdrawal},
+    testing::{
+        secs, ActuatorCall, AuthenticatorCall, LockerCall, MockActuator, MockAuthenticator,
+        MockDetector, MockDevice, MockError, MockLocker,
+    },
+};
+
+/// Runs the daemon until `duration` passed
+async fn run_for<Detect, Auth>(
+    manager: &mut Manager<Detect, Auth, MockActuator, MockLocker>,
+    duration: Duration,
+) where
+    Detect: Detector<Device = MockDevice> + Sync,
+    Auth: Authenticate<Device = MockDevice> + Sync,
+{
+    let shutdown = CancellationToken::new();
+    let cancel = shutdown.clone();
+    tokio::spawn(async move {
+        sleep(duration).await;
+        cancel.cancel();
+    });
+    manager.daemon(shutdown).await.unwrap();
+}
+
+/// When the calls were made, relative to `start`
+fn times<C: PartialEq>(calls: Vec<(Instant, C)>, call: &C, start: Instant) -> Vec<Duration> {
+    calls
+        .into_iter()
+        .filter(|(_, c)| c == call)
+        .map(|(at, _)| at - start)
+        .collect()
+}
+
+fn authenticated<D>(calls: Vec<(Instant, AuthenticatorCall<D>)>, start: Instant) -> Vec<Duration> {
+    calls
+        .into_iter()
+        .filter(|(_, call)| matches!(call, AuthenticatorCall::Authenticate(..)))
+        .map(|(at, _)| at - start)
+        .collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn denied_device_is_ignored_during_cooldown() {
+    let start = Instant::now();
+    let device = MockDevice::new("phone");
+    let detector = MockDetector::new()
+        .detect(secs(0), device.clone())
+        .detect(secs(5), device.clone())
+        .detect(secs(60), device);
+    let auth = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Deny);
+    let locker = MockLocker::new().lock(secs(0));
+
+    let mut manager = Manager::new(
+        detector,
+        auth.clone(),
+        MockActuator::new(),
+        locker,
+        Config::default(),
+    );
+    run_for(&mut manager, secs(100)).await;
+
+    // detected at 6s within the 30s cooldown, at 66s after it
+    assert_eq!(authenticated(auth.calls(), start), vec![secs(0), secs(66)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn door_relocks_after_extended_hold_open() {
+    let start = Instant::now();
+    let detector = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    let auth = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Allow);
+    let locker = MockLocker::new()
+        .lock(secs(0))
+        .hold_open(secs(5), HoldOpen::Extend);
+    let config = Config {
+        relock_timeout: Some(secs(10)),
+        ..Config::default()
+    };
+
+    let mut manager = Manager::new(detector, auth, MockActuator::new(), locker.clone(), config);
+    run_for(&mut manager, secs(100)).await;
+
+    // opened at 1s, extended at 6s, relocked 10s later
+    assert_eq!(
+        times(locker.calls(), &LockerCall::ConfirmLock, start),
+        vec![secs(0), secs(16)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn escalation_asks_next_approver_after_timeout() {
+    let start = Instant::now();
+    let detector = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    let first = MockAuthenticator::new();
+    let second = MockAuthenticator::new().answer(secs(3), AuthenticateResult::Allow);
+    let auth = Escalation::new(
+        vec![
+            (first.clone(), Some(secs(10))),
+            (second.clone(), Some(secs(10))),
+        ],
+        AuthenticateResult::Timeout,
+    );
+    let act = MockActuator::new();
+    let locker = MockLocker::new().lock(secs(0));
+
+    let mut manager = Manager::new(detector, auth, act.clone(), locker, Config::default());
+    run_for(&mut manager, secs(100)).await;
+
+    assert_eq!(authenticated(first.calls(), start), vec![secs(0)]);
+    assert_eq!(authenticated(second.calls(), start), vec![secs(10)]);
+    assert_eq!(
+        times(act.calls(), &ActuatorCall::Open, start),
+        vec![secs(13)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn departure_withdraws_authentication() {
+    let start = Instant::now();
+    let detector = MockDetector::new()
+        .detect(secs(0), MockDevice::new("phone"))
+        .detect(secs(0), MockDevice::new("tablet"))
+        .depart(secs(5));
+    let auth = MockAuthenticator::new()
+        .answer(secs(1), AuthenticateResult::Allow)
+        .answer(secs(10), AuthenticateResult::Allow);
+    let act = MockActuator::new();
+    let locker = MockLocker::new().lock(secs(0)).lock(secs(2));
+
+    let mut manager = Manager::new(
+        detector,
+        auth.clone(),
+        act.clone(),
+        locker,
+        Config::default(),
+    );
+    run_for(&mut manager, secs(100)).await;
+
+    // the phone was let in before the departure, the tablet left 5s into its attempt at 3s
+    assert_eq!(authenticated(auth.calls(), start), vec![secs(0), secs(3)]);
+    assert_eq!(
+        times(
+            auth.calls(),
+            &AuthenticatorCall::Withdraw(Withdrawal::Left),
+            start
+        ),
+        vec![secs(8)]
+    );
+    assert_eq!(act.opened(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn device_gone_while_door_was_open_is_not_let_in() {
+    let resident = MockDevice::new("resident").with_access(Access {
+        trust: Trust::Allow,
+        ..Access::default()
+    });
+    let detector = Presence::new(MockDetector::new().detect(secs(1), resident), secs(10));
+    let act = MockActuator::new();
+    // the door is only locked long after the resident left
+    let locker = MockLocker::new().lock(secs(3600));
+
+    let mut manager = Manager::new(
+        detector,
+        MockAuthenticator::new(),
+        act.clone(),
+        locker,
+        Config::default(),
+    );
+    run_for(&mut manager, secs(4000)).await;
+
+    assert_eq!(act.opened(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn error_report_names_device_by_display() {
+    let detector = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    let auth = MockAuthenticator::new().fail(secs(1), MockError::fatal("offline"));
+    let locker = MockLocker::new().lock(secs(0));
+
+    let mut manager = Manager::new(
+        detector,
+        auth,
+        MockActuator::new(),
+        locker,
+        Config::default(),
+    );
+    let report = manager
+        .daemon(CancellationToken::new())
+        .await
+        .unwrap_err()
+        .report();
+
+    // not the debug output, which includes the access rules
+    assert_eq!(report.device.as_deref(), Some("phone"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn dry_run_does_not_wait_for_relock() {
+    let start = Instant::now();
+    let detector = MockDetector::new()
+        .detect(secs(0), MockDevice::new("phone"))
+        .detect(secs(60), MockDevice::new("tablet"));
+    let auth = MockAuthenticator::new()
+        .answer(secs(1), AuthenticateResult::Allow)
+        .answer(secs(1), AuthenticateResult::Allow);
+    let act = MockActuator::new();
+    let locker = MockLocker::new().lock(secs(0));
+    let config = Config {
+        dry_run: true,
+        unlocked_alert: Some(secs(10)),
+        ..Config::default()
+    };
+
+    let mut manager = Manager::new(detector, auth.clone(), act.clone(), locker, config);
+    run_for(&mut manager, secs(100)).await;
+
+    // both devices were handled, nobody was alerted of an unlocked door
+    assert_eq!(authenticated(auth.calls(), start), vec![secs(0), secs(61)]);
+    let alerted = auth.calls().into_iter().any(|(_, call)| match call {
+        AuthenticatorCall::Inform(notice) => notice.starts_with("LeftUnlocked"),
+        _ => false,
+    });
+    assert!(!alerted);
+    assert_eq!(act.opened(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn locker_is_asked_to_hold_open_once_per_request() {
+    let start = Instant::now();
+    let detector = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    let auth = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Allow);
+    let locker = MockLocker::new()
+        .lock(secs(0))
+        .hold_open(secs(5), HoldOpen::Extend);
+    let config = Config {
+        relock_timeout: Some(secs(10)),
+        unlocked_alert: Some(secs(2)),
+        unlocked_reminder: secs(2),
+        ..Config::default()
+    };
+
+    let mut manager = Manager::new(detector, auth, MockActuator::new(), locker.clone(), config);
+    run_for(&mut manager, secs(100)).await;
+
+    // asked when the door opened at 1s and again after the extension at 6s,
+    // alerts in between do not ask again; whether the startup lock asks depends on polling order
+    let asked = times(locker.calls(), &LockerCall::HoldOpen, start)
+        .into_iter()
+        .filter(|at| *at >= secs(1))
+        .collect::<Vec<_>>();
+    assert_eq!(asked, vec![secs(1), secs(6)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn acknowledged_alert_restarts_alerts() {
+    let detector = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    let auth = MockAuthenticator::new()
+        .answer(secs(1), AuthenticateResult::Allow)
+        .acknowledge(secs(5));
+    let locker = MockLocker::new().lock(secs(0));
+    let config = Config {
+        unlocked_alert: Some(secs(10)),
+        unlocked_reminder: secs(10),
+        unlocked_escalate_after: Some(1),
+        ..Config::default()
+    };
+
+    let mut manager = Manager::new(detector, auth.clone(), MockActuator::new(), locker, config);
+    run_for(&mut manager, secs(40)).await;
+
+    // acknowledged 5s after the first alert, the next alert is not escalated
+    let alerts = auth
+        .calls()
+        .into_iter()
+        .filter_map(|(_, call)| match call {
+            AuthenticatorCall::Inform(notice) => Some(notice),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        alerts,
+        vec![
+            "LeftUnlocked(10s, false)",
+            "LeftUnlocked(25s, false)",
+            "LeftUnlocked(35s, true)"
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn failing_daemon_locks_the_door() {
+    let start = Instant::now();
+    let detector = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    let auth = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Allow);
+    let act = MockActuator::new().fail(MockError::fatal("jammed"));
+    let locker = MockLocker::new().lock(secs(0));
+
+    let mut manager = Manager::new(detector, auth, act, locker.clone(), Config::default());
+    assert!(manager.daemon(CancellationToken::new()).await.is_err());
+
+    assert_eq!(
+        times(locker.calls(), &LockerCall::ConfirmLock, start),
+        vec![secs(0), secs(1)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn failing_daemon_withdraws_pending_request() {
+    let detector = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    let first = MockAuthenticator::new();
+    let second = MockAuthenticator::new().fail(secs(1), MockError::fatal("offline"));
+    let auth = Approvers::any(vec![first.clone(), second]).unwrap();
+    let locker = MockLocker::new().lock(secs(0));
+
+    let mut manager = Manager::new(
+        detector,
+        auth,
+        MockActuator::new(),
+        locker,
+        Config::default(),
+    );
+    assert!(manager.daemon(CancellationToken::new()).await.is_err());
+
+    assert!(first
+        .calls()
+        .iter()
+        .any(|(_, call)| *call == AuthenticatorCall::Withdraw(Withdrawal::Shutdown)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn device_blocked_on_one_detector_is_blocked_on_the_other() {
+    let start = Instant::now();
+    let bluetooth = MockDetector::new().detect(secs(0), MockDevice::new("phone"));
+    // asked again once the phone was blocked at 1s, long after the cooldown
+    let stdin = MockDetector::new().detect(secs(100), MockDevice::new("phone"));
+    let auth = MockAuthenticator::new()
+        .answer(secs(1), AuthenticateResult::DenyAndBlock)
+        .answer(secs(1), AuthenticateResult::Allow);
+    let locker = MockLocker::new().lock(secs(0));
+
+    let mut manager = Manager::new(
+        Race::new(bluetooth, stdin),
+        auth.clone(),
+        MockActuator::new(),
+        locker,
+        Config::default(),
+    );
+    let shutdown = CancellationToken::new();
+    let cancel = shutdown.clone();
+    tokio::spawn(async move {
+        sleep(secs(200)).await;
+        cancel.cancel();
+    });
+    manager.daemon(shutdown).await.unwrap();
+
+    assert_eq!(authenticated(auth.calls(), start), vec![secs(0)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn granted_device_is_let_in_without_asking_until_the_grant_expires() {
+    let start = Instant::now();
+    let detector = MockDetector::new()
+        .detect(secs(0), MockDevice::new("phone"))
+        .detect(secs(10), MockDevice::new("phone"))
+        .detect(secs(100), MockDevice::new("phone"));
+    let auth = MockAuthenticator::new()
+        .answer(secs(1), AuthenticateResult::AllowFor(secs(60)))
+        .answer(secs(1), AuthenticateResult::Allow);
+    let act = MockActuator::new();
+    let locker = MockLocker::new()
+        .lock(secs(0))
+        .lock(secs(1))
+        .lock(secs(1))
+        .lock(secs(1));
+
+    let mut manager = Manager::new(
+        detector,
+        auth.clone(),
+        act.clone(),
+        locker,
+        Config::default(),
+    );
+    run_for(&mut manager, secs(200)).await;
+
+    // granted at 1s, let in at 12s without asking, asked again at 113s after the grant expired
+    assert_eq!(authenticated(auth.calls(), start), vec![secs(0), secs(113)]);
+    assert_eq!(act.opened(), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn blocked_device_loses_its_grant_and_is_locked_out() {
+    let start = Instant::now();
+    let phone = MockDevice::new("phone");
+    let detector = MockDetector::new()
+        .detect(secs(0), phone.clone())
+        .detect(secs(100), phone.clone());
+    let auth = MockAuthenticator::new().answer(secs(1), AuthenticateResult::DenyAndBlock);
+    let act = MockActuator::new();
+    let locker = MockLocker::new().lock(secs(0));
+
+    let mut manager = Manager::new(
+        detector,
+        auth.clone(),
+        act.clone(),
+        locker,
+        Config::default(),
+    );
+    let grants = manager.grants();
+    let backoff = manager.backoff();
+    // another approver granted access while the prompt was pending
+    let granted = grants.clone();
+    let device = phone.clone();
+    tokio::spawn(async move {
+        sleep(Duration::from_millis(500)).await;
+        granted.grant(&device, secs(3600));
+    });
+    run_for(&mut manager, secs(200)).await;
+
+    // the detection at 101s is ignored
+    assert_eq!(authenticated(auth.calls(), start), vec![secs(0)]);
+    assert_eq!(grants.granted(&phone), None);
+    assert_eq!(backoff.locked_out(), vec![phone]);
+    assert_eq!(act.opened(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn denial_with_reason_counts_as_denial() {
+    let start = Instant::now();
+    let phone = MockDevice::new("phone");
+    let detector = MockDetector::new()
+        .detect(secs(0), phone.clone())
+        .detect(secs(10), phone.clone())
+        .detect(secs(50), phone.clone())
+        .detect(secs(200), phone.clone());
+    let auth = MockAuthenticator::new()
+        .answer(
+            secs(1),
+            AuthenticateResult::DenyWithReason("not expected".to_string()),
+        )
+        .answer(
+            secs(1),
+            AuthenticateResult::DenyWithReason("still not expected".to_string()),
+        );
+    let locker = MockLocker::new().lock(secs(0));
+    let config = Config {
+        lockout_after: Some(2),
+        ..Config::default()
+    };
+
+    let mut manager = Manager::new(detector, auth.clone(), MockActuator::new(), locker, config);
+    let backoff = manager.backoff();
+    run_for(&mut manager, secs(400)).await;
+
+    // ignored at 11s during the cooldown, asked again at 61s, locked out after the second denial
+    assert_eq!(authenticated(auth.calls(), start), vec![secs(0), secs(61)]);
+    assert_eq!(backoff.locked_out(), vec![phone]);
+}
@@ -0,0 +1,200 @@
+use std::{convert::Infallible, fmt::Display, future::Future, net::SocketAddr};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Response, Server,
+};
+use log::{error, info};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    interfaces::services::AuthenticateResult,
+    manager::{Actuation, DoorState, Event, Observer},
+};
+
+const STATES: [DoorState; 6] = [
+    DoorState::Locked,
+    DoorState::Detecting,
+    DoorState::Authenticating,
+    DoorState::Opening,
+    DoorState::Open,
+    DoorState::Error,
+];
+
+/// Prometheus metrics of a manager
+///
+/// Cloning yields a handle to the same metrics,
+/// register it with [`Manager::observe`](crate::manager::Manager::observe)
+/// and follow the state with [`Metrics::track`].
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    detections: IntCounterVec,
+    decisions: IntCounterVec,
+    latency: HistogramVec,
+    actuator_failures: IntCounter,
+    bluetooth_events: IntCounter,
+    state: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("doorman".to_string()), None)?;
+
+        let detections = IntCounterVec::new(
+            Opts::new("detections_total", "Detected registered devices"),
+            &["device"],
+        )?;
+        let decisions = IntCounterVec::new(
+            Opts::new("decisions_total", "Decisions on detected devices"),
+            &["decision"],
+        )?;
+        let latency = HistogramVec::new(
+            HistogramOpts::new("authentication_seconds", "Time from detection to decision")
+                .buckets(vec![0.1, 1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0]),
+            &["decision"],
+        )?;
+        let actuator_failures = IntCounter::new("actuator_failures_total", "Failed openings")?;
+        let bluetooth_events =
+            IntCounter::new("bluetooth_events_total", "Processed bluetooth events")?;
+        let state = IntGaugeVec::new(
+            Opts::new("state", "Current state of the manager"),
+            &["state"],
+        )?;
+
+        registry.register(Box::new(detections.clone()))?;
+        registry.register(Box::new(decisions.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+        registry.register(Box::new(actuator_failures.clone()))?;
+        registry.register(Box::new(bluetooth_events.clone()))?;
+        registry.register(Box::new(state.clone()))?;
+
+        Ok(Self {
+            registry,
+            detections,
+            decisions,
+            latency,
+            actuator_failures,
+            bluetooth_events,
+            state,
+        })
+    }
+
+    /// Counts an event processed by a bluetooth detector
+    pub fn bluetooth_event(&self) {
+        self.bluetooth_events.inc();
+    }
+
+    /// Follows the state of a manager, see [`Manager::subscribe`](crate::manager::Manager::subscribe)
+    pub fn track(&self, mut states: watch::Receiver<DoorState>) {
+        let gauge = self.state.clone();
+        tokio::spawn(async move {
+            loop {
+                let current = *states.borrow();
+                for state in STATES.iter() {
+                    gauge
+                        .with_label_values(&[&state.to_string()])
+                        .set((*state == current) as i64);
+                }
+                if states.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn render(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!("Could not encode metrics: {}", e);
+        }
+        buffer
+    }
+
+    /// Binds `addr` and returns a future serving the metrics over HTTP until `shutdown` is cancelled
+    ///
+    /// Fails right away if the address cannot be bound, e.g. because it is in use.
+    pub fn serve(
+        self,
+        addr: SocketAddr,
+        shutdown: CancellationToken,
+    ) -> hyper::Result<impl Future<Output = hyper::Result<()>>> {
+        let server = Server::try_bind(&addr)?;
+        let make_service = make_service_fn(move |_| {
+            let metrics = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_| {
+                    let body = metrics.render();
+                    async move {
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .header(CONTENT_TYPE, TextEncoder::new().format_type())
+                                .body(Body::from(body))
+                                .expect("valid response"),
+                        )
+                    }
+                }))
+            }
+        });
+
+        info!("Serving metrics on http://{}/metrics", addr);
+        Ok(server
+            .serve(make_service)
+            .with_graceful_shutdown(async move { shutdown.cancelled().await }))
+    }
+}
+
+impl<Device: Display> Observer<Device> for Metrics {
+    fn notify(&self, event: &Event<'_, Device>) {
+        match event {
+            Event::DeviceDetected(device) => {
+                self.detections
+                    .with_label_values(&[&device.to_string()])
+                    .inc();
+            }
+            Event::Attempt(attempt) => {
+                let decision = match attempt.decision {
+                    AuthenticateResult::Allow => "allow",
+                    AuthenticateResult::AllowFor(_) => "allow_for",
+                    AuthenticateResult::Deny => "deny",
+                    AuthenticateResult::DenyWithReason(_) => "deny_with_reason",
+                    AuthenticateResult::DenyAndBlock => "deny_and_block",
+                    AuthenticateResult::Timeout => "timeout",
+                };
+                self.decisions.with_label_values(&[decision]).inc();
+                self.latency
+                    .with_label_values(&[decision])
+                    .observe(attempt.latency.as_secs_f64());
+                if let Actuation::Failed(_) = attempt.actuation {
+                    self.actuator_failures.inc();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::Metrics;
+
+    #[tokio::test]
+    async fn serve_fails_on_address_in_use() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+
+        let metrics = Metrics::new().unwrap();
+        assert!(metrics.serve(addr, CancellationToken::new()).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::{collections::HashMap, fs::File, hash::Hash, io::BufReader, path::PathBuf};
 
 use crate::interfaces::services::{self, Registry as RegistryTrait, ServiceError};
-use serde::{de::DeserializeOwned, Deserialize};
+use serde::de::DeserializeOwned;
 use thiserror::Error;
 
 #[derive(Debug, Default)]
@@ -37,7 +37,7 @@ impl<Ident: Hash + Eq, Device> services::Registry for Registry<Ident, Device> {
     }
 
     fn unregister_device(&mut self, ident: &Self::Ident) -> Result<(), Self::RegistryError> {
-        if self.devices.remove(&ident).is_none() {
+        if self.devices.remove(ident).is_none() {
             return Err(RegistryError::NotFoundError);
         }
         Ok(())
@@ -2,23 +2,35 @@ use async_trait::async_trait;
 
 use doorman::interfaces::services::{self, ServiceError};
 use log::info;
-use std::{fmt::Display, io::{self, BufRead}, marker::PhantomData, time::Duration};
+use std::{fmt::Display, io, marker::PhantomData, time::Duration};
 use thiserror::Error;
+
+use super::input::Input;
 #[derive(Debug, Error)]
 pub enum AuthenticatorError {
     #[error("EOL without device found")]
     EOLError,
+
+    #[error("Error reading input: {0}")]
+    IO(#[from] io::Error),
 }
 
-impl ServiceError for AuthenticatorError {}
+impl ServiceError for AuthenticatorError {
+    fn is_fatal(&self) -> bool {
+        // stdin is closed for good
+        matches!(self, AuthenticatorError::EOLError)
+    }
+}
 
 pub struct Authenticator<D: Display> {
+    input: Input,
     marker: PhantomData<D>,
 }
 
 impl<D: Display> Authenticator<D> {
-    pub fn new() -> Self {
+    pub fn new(input: Input) -> Self {
         Self {
+            input,
             marker: PhantomData,
         }
     }
@@ -35,24 +47,79 @@ impl<D: Display + std::marker::Sync> services::Authenticate for Authenticator<D>
         device: &Self::Device,
         _: Option<Duration>,
     ) -> Result<services::AuthenticateResult, Self::AuthenticateError> {
-        let stdin = io::stdin();
-        println!("Device {} device detected.\n open (y)es, (N)o", device);
+        println!(
+            "Device {} device detected.\n open (y)es [minutes without asking], (N)o [reason], (b)lock",
+            device
+        );
 
-        let mut input = String::new();
-        while input.is_empty() {
-            stdin.lock().read_line(&mut input).unwrap();
+        while let Some(input) = self.input.next_line().await? {
+            let (answer, rest) = match input.trim().split_once(' ') {
+                Some((answer, rest)) => (answer, rest.trim()),
+                None => (input.trim(), ""),
+            };
 
-            if ["yes", "y"].contains(&input.trim()) {
-                info!("allow");
-                return Ok(services::AuthenticateResult::Allow);
-            } else if ["no", "n", ""].contains(&input.trim()) {
-                info!("deny");
-
-                return Ok(services::AuthenticateResult::Deny);
+            if ["yes", "y"].contains(&answer) {
+                match rest.parse::<u64>() {
+                    Ok(minutes) => {
+                        info!("allow for {} minutes", minutes);
+                        return Ok(services::AuthenticateResult::AllowFor(Duration::from_secs(
+                            minutes.saturating_mul(60),
+                        )));
+                    }
+                    Err(_) if rest.is_empty() => {
+                        info!("allow");
+                        return Ok(services::AuthenticateResult::Allow);
+                    }
+                    Err(_) => println!("Not a number of minutes: {}", rest),
+                }
+            } else if ["no", "n", ""].contains(&answer) {
+                if rest.is_empty() {
+                    info!("deny");
+                    return Ok(services::AuthenticateResult::Deny);
+                }
+                info!("deny: {}", rest);
+                return Ok(services::AuthenticateResult::DenyWithReason(
+                    rest.to_string(),
+                ));
+            } else if ["block", "b"].contains(&answer) {
+                info!("block");
+                return Ok(services::AuthenticateResult::DenyAndBlock);
             }
-            input = String::new();
         }
 
         Err(AuthenticatorError::EOLError)
     }
+
+    async fn inform(
+        &self,
+        notice: &services::Notice<'_, Self::Device>,
+    ) -> Result<(), Self::AuthenticateError> {
+        match notice {
+            services::Notice::WouldOpen(device) => {
+                println!("Dry run: would have opened the door for {}", device)
+            }
+            services::Notice::AutoAllowed(device) => {
+                println!("Opened the door for trusted device {}", device)
+            }
+            services::Notice::LeftUnlocked(open_for, escalated) => {
+                if *escalated {
+                    print!("Nobody responded: ");
+                }
+                println!(
+                    "The door has been unlocked for {} min",
+                    open_for.as_secs() / 60
+                )
+            }
+        }
+        Ok(())
+    }
+
+    fn approver(&self) -> Option<String> {
+        Some("stdin".to_string())
+    }
+
+    async fn withdraw(&self, reason: services::Withdrawal) -> Result<(), Self::AuthenticateError> {
+        println!("Withdrawn: {}", reason);
+        Ok(())
+    }
 }
@@ -1,42 +1,57 @@
-use super::device::SimpleDevice;
 use async_trait::async_trait;
 use doorman::interfaces::services::{self, Registry, ServiceError};
-use std::io::{self, BufRead};
+use std::{fmt::Debug, io, sync::Arc};
 use thiserror::Error;
+
+use super::input::Input;
 #[derive(Debug, Error)]
 pub enum DetectorError {
     #[error("EOL without device found")]
     EOLError,
+
+    #[error("Error reading input: {0}")]
+    IO(#[from] io::Error),
 }
 
-impl ServiceError for DetectorError {}
+impl ServiceError for DetectorError {
+    fn is_fatal(&self) -> bool {
+        // stdin is closed for good
+        matches!(self, DetectorError::EOLError)
+    }
+}
 
-pub struct Detector<'a, Reg: Registry<Device = SimpleDevice> + Send + Sync> {
-    registry: &'a Reg,
+/// Detects devices by reading their identifier from stdin
+pub struct Detector<Reg: Registry + Send + Sync> {
+    registry: Arc<Reg>,
+    input: Input,
 }
 
-impl<'a, Reg: Registry<Device = SimpleDevice> + Send + Sync> Detector<'a, Reg> {
-    pub fn new(registry: &'a Reg) -> Self {
-        Self { registry }
+impl<Reg: Registry + Send + Sync> Detector<Reg> {
+    pub fn new(registry: Arc<Reg>, input: Input) -> Self {
+        Self { registry, input }
     }
 }
 
 #[async_trait]
-impl<'a, Reg: Registry<Device = SimpleDevice, Ident = SimpleDevice> + Send + Sync>
-    services::Detector for Detector<'a, Reg>
+impl<Reg> services::Detector for Detector<Reg>
+where
+    Reg: Registry + Send + Sync,
+    Reg::Ident: From<String>,
+    Reg::Device: Debug + Clone + Send,
 {
-    type Device = SimpleDevice;
+    type Device = Reg::Device;
     type DetectorError = DetectorError;
 
-    async fn wait_for_device(&self) -> Result<&Self::Device, Self::DetectorError> {
-        let stdin = io::stdin();
-        for line in stdin.lock().lines() {
-            let device = SimpleDevice(line.unwrap());
-
-            if let Some(device) = self.registry.check(&device) {
-                return Ok(device);
+    async fn wait_for_device(&self) -> Result<Self::Device, Self::DetectorError> {
+        while let Some(line) = self.input.next_line().await? {
+            if let Some(device) = self.registry.check(&line.into()) {
+                return Ok(device.clone());
             };
         }
         Err(DetectorError::EOLError)
     }
+
+    fn source(&self, _device: &Self::Device) -> String {
+        "stdin".to_string()
+    }
 }
@@ -1,11 +1,65 @@
+use std::hash::{Hash, Hasher};
+
 use derive_more::Display;
+use doorman::access::{Access, Restricted};
 use serde::{Deserialize, Serialize};
 
-#[derive(Hash, PartialEq, Eq, Debug, Display, Clone, Serialize, Deserialize)]
-pub struct SimpleDevice(pub String);
+/// A device known by its name, registered either as plain name or with access rules
+#[derive(Debug, Display, Clone, Serialize, Deserialize)]
+#[display(fmt = "{}", name)]
+#[serde(from = "Entry")]
+pub struct SimpleDevice {
+    pub name: String,
+    #[serde(flatten)]
+    pub access: Access,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Name(String),
+    Device {
+        name: String,
+        #[serde(flatten)]
+        access: Access,
+    },
+}
+
+impl From<Entry> for SimpleDevice {
+    fn from(entry: Entry) -> Self {
+        match entry {
+            Entry::Name(name) => name.into(),
+            Entry::Device { name, access } => SimpleDevice { name, access },
+        }
+    }
+}
 
 impl<S: AsRef<str>> From<S> for SimpleDevice {
     fn from(s: S) -> Self {
-        SimpleDevice(s.as_ref().to_string())
+        SimpleDevice {
+            name: s.as_ref().to_string(),
+            access: Access::default(),
+        }
+    }
+}
+
+// devices are identified by their name only, so that typed names find registered devices
+impl PartialEq for SimpleDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for SimpleDevice {}
+
+impl Hash for SimpleDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl Restricted for SimpleDevice {
+    fn access(&self) -> &Access {
+        &self.access
     }
 }
@@ -0,0 +1,32 @@
+use std::{io, sync::Arc};
+
+use tokio::{
+    io::{stdin, AsyncBufReadExt, AsyncRead, BufReader, Lines},
+    sync::Mutex,
+};
+
+type Reader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Line based stdin shared by all simple services
+///
+/// Reading through a single buffer ensures no line gets lost
+/// if a pending read is cancelled.
+#[derive(Clone)]
+pub struct Input(Arc<Mutex<Lines<BufReader<Reader>>>>);
+
+impl Input {
+    pub fn new() -> Self {
+        Self::from_reader(stdin())
+    }
+
+    /// Reads lines from `reader` instead of stdin
+    pub fn from_reader(reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        let reader: Reader = Box::new(reader);
+        Self(Arc::new(Mutex::new(BufReader::new(reader).lines())))
+    }
+
+    /// Reads the next line, `None` at EOF
+    pub async fn next_line(&self) -> io::Result<Option<String>> {
+        self.0.lock().await.next_line().await
+    }
+}
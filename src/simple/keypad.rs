@@ -0,0 +1,58 @@
+use std::{io, time::Duration};
+
+use async_trait::async_trait;
+use doorman::interfaces::services::{self, ServiceError};
+use thiserror::Error;
+use tokio::time::timeout as within;
+
+use super::input::Input;
+
+#[derive(Debug, Error)]
+pub enum KeypadError {
+    #[error("EOL without PIN entered")]
+    EOLError,
+
+    #[error("Error reading input: {0}")]
+    IO(#[from] io::Error),
+}
+
+impl ServiceError for KeypadError {
+    fn is_fatal(&self) -> bool {
+        // stdin is closed for good
+        matches!(self, KeypadError::EOLError)
+    }
+}
+
+/// Reads PINs from stdin
+pub struct Keypad {
+    input: Input,
+}
+
+impl Keypad {
+    pub fn new(input: Input) -> Self {
+        Self { input }
+    }
+}
+
+#[async_trait]
+impl services::Keypad for Keypad {
+    type KeypadError = KeypadError;
+
+    async fn read_pin(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<String>, Self::KeypadError> {
+        println!("Enter PIN:");
+
+        let line = match timeout {
+            Some(timeout) => match within(timeout, self.input.next_line()).await {
+                Ok(line) => line?,
+                Err(_) => return Ok(None),
+            },
+            None => self.input.next_line().await?,
+        };
+
+        line.map(|pin| Some(pin.trim().to_string()))
+            .ok_or(KeypadError::EOLError)
+    }
+}
@@ -1,24 +1,28 @@
-use std::io::{self, BufRead, stdin};
+use std::io;
 
 use async_trait::async_trait;
 
 use doorman::interfaces::services::{self, ServiceError};
 use thiserror::Error;
 
+use super::input::Input;
+
 
 #[derive(Debug, Error)]
 pub enum LockerError {
     #[error("Input reached EOF ({0})")]
-    EOF(#[from] io::Error)
+    Eof(#[from] io::Error)
 }
 
 impl ServiceError for LockerError {}
 
-pub struct Locker;
+pub struct Locker {
+    input: Input,
+}
 
 impl Locker {
-    pub fn new() -> Self {
-        Self
+    pub fn new(input: Input) -> Self {
+        Self { input }
     }
 }
 
@@ -29,8 +33,7 @@ impl services::Locker for Locker {
 
     async fn wait_for_lock(&self) -> Result<(), Self::LockerError> {
         println!("Press [Enter] to lock...");
-        let mut buf = String::new();
-        stdin().lock().read_line(&mut buf)?;
+        self.input.next_line().await?;
         Ok(())
     }
 
@@ -1,5 +1,11 @@
 pub mod actuator;
 pub mod authenticator;
 pub mod detector;
+#[cfg_attr(feature = "bluetooth", allow(dead_code))]
 pub mod device;
+pub mod input;
+pub mod keypad;
 pub mod locker;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,83 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use doorman::{
+    interfaces::services::Registry as _,
+    manager::{Config, Event, Manager},
+    registry::Registry,
+    supervisor::Supervisor,
+};
+use tokio::{
+    io::{duplex, AsyncWriteExt},
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    actuator::Actuator, authenticator::Authenticator, detector::Detector, device::SimpleDevice,
+    input::Input, locker::Locker,
+};
+
+const fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+#[tokio::test(start_paused = true)]
+async fn lines_reach_the_service_waiting_for_them() {
+    let (mut typed, reader) = duplex(64);
+    let input = Input::from_reader(reader);
+
+    let mut registry: Registry<SimpleDevice, SimpleDevice> = Registry::new();
+    registry
+        .from_list(vec![SimpleDevice::from("phone")])
+        .unwrap();
+    let detector = Detector::new(Arc::new(registry), input.clone());
+
+    let shutdown = CancellationToken::new();
+    let mut supervisor = Supervisor::new(shutdown.clone());
+    let door = supervisor.door("main");
+    supervisor.detector(detector, &["main"]).unwrap();
+
+    let mut manager = Manager::new(
+        door,
+        Authenticator::new(input.clone()),
+        Actuator,
+        Locker::new(input),
+        Config::default(),
+    );
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let observed = events.clone();
+    manager.observe(move |event: &Event<'_, SimpleDevice>| {
+        let event = match event {
+            Event::DeviceDetected(device) => format!("detected {}", device),
+            Event::Allowed(device) => format!("allowed {}", device),
+            Event::LockConfirmed => "locked".to_string(),
+            _ => return,
+        };
+        observed.lock().unwrap().push(event);
+    });
+    supervisor.manage("main", manager);
+
+    // lock at startup, the phone arrives, is allowed and the door locked again
+    tokio::spawn(async move {
+        for line in &["", "phone", "y", ""] {
+            sleep(secs(1)).await;
+            typed
+                .write_all(format!("{}\n", line).as_bytes())
+                .await
+                .unwrap();
+        }
+        sleep(secs(1)).await;
+        shutdown.cancel();
+        // keeps the input open until the door stopped
+        sleep(secs(60)).await;
+    });
+    supervisor.run().await.unwrap();
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["locked", "detected phone", "allowed phone", "locked"]
+    );
+}
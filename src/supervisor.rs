@@ -0,0 +1,744 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use log::{debug, error, info, warn};
+use thiserror::Error;
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        watch, Mutex,
+    },
+    task::{JoinError, JoinHandle},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    access::Restricted,
+    interfaces::services::{Actuator, Authenticate, Detector, Locker, ServiceError},
+    manager::Manager,
+};
+
+#[derive(Debug, Error)]
+pub enum SupervisorError {
+    #[error("Unknown door {0}")]
+    UnknownDoor(String),
+
+    #[error("Door {door} failed: {error}")]
+    Door {
+        door: String,
+        error: Box<dyn ServiceError>,
+    },
+
+    #[error("Task failed: {0}")]
+    Join(#[from] JoinError),
+}
+
+impl ServiceError for SupervisorError {
+    fn is_fatal(&self) -> bool {
+        match self {
+            SupervisorError::Door { error, .. } => error.is_fatal(),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DoorDetectorError {
+    #[error("Door {0} no longer receives detections")]
+    Closed(String),
+
+    #[error("{0}")]
+    Detector(Arc<dyn ServiceError>),
+}
+
+impl ServiceError for DoorDetectorError {
+    fn is_fatal(&self) -> bool {
+        match self {
+            DoorDetectorError::Closed(_) => true,
+            DoorDetectorError::Detector(error) => error.is_fatal(),
+        }
+    }
+}
+
+/// Resolves once the device left the range of the detector that detected it
+type Departure<Device> = Arc<dyn Fn(Device) -> BoxFuture<'static, ()> + Send + Sync>;
+/// Restarts a routed detector if it failed since it was last restarted
+type Restart = Arc<dyn Fn() -> BoxFuture<'static, Result<(), Arc<dyn ServiceError>>> + Send + Sync>;
+type Routed<Device> = Result<Detection<Device>, Arc<dyn ServiceError>>;
+
+/// A detection routed to a door
+struct Detection<Device> {
+    device: Device,
+    source: String,
+    departure: Departure<Device>,
+}
+
+impl<Device: Clone> Clone for Detection<Device> {
+    fn clone(&self) -> Self {
+        Self {
+            device: self.device.clone(),
+            source: self.source.clone(),
+            departure: self.departure.clone(),
+        }
+    }
+}
+
+/// Where the routers send the detections of a door
+struct DoorSender<Device> {
+    sender: mpsc::Sender<Routed<Device>>,
+    /// Set while the door waits for a device, detectors are only polled while one of their doors waits
+    waiting: Arc<watch::Sender<bool>>,
+    waiting_changes: watch::Receiver<bool>,
+    /// Restarts of the detectors routed to the door
+    restarts: Arc<std::sync::Mutex<Vec<Restart>>>,
+}
+
+impl<Device> Clone for DoorSender<Device> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            waiting: self.waiting.clone(),
+            waiting_changes: self.waiting_changes.clone(),
+            restarts: self.restarts.clone(),
+        }
+    }
+}
+
+/// Marks a door as waiting for a device until dropped
+struct Waiting<'a>(&'a watch::Sender<bool>);
+
+impl<'a> Waiting<'a> {
+    fn new(waiting: &'a watch::Sender<bool>) -> Self {
+        // fails without routers, nobody to tell
+        let _ = waiting.send(true);
+        Self(waiting)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.send(false);
+    }
+}
+
+/// Hands the detections routed to a door to its [`Manager`]
+///
+/// Departures are awaited on the detector the device was detected by,
+/// restarting restarts the routed detectors that failed.
+pub struct DoorDetector<Device> {
+    door: String,
+    detections: Mutex<mpsc::Receiver<Routed<Device>>>,
+    waiting: Arc<watch::Sender<bool>>,
+    restarts: Arc<std::sync::Mutex<Vec<Restart>>>,
+    /// Source and departure of the most recently handed out detection
+    last: std::sync::Mutex<Option<(String, Departure<Device>)>>,
+}
+
+#[async_trait]
+impl<Device: Debug + Clone + Send> Detector for DoorDetector<Device> {
+    type Device = Device;
+    type DetectorError = DoorDetectorError;
+
+    async fn wait_for_device(&self) -> Result<Self::Device, Self::DetectorError> {
+        let mut detections = self.detections.lock().await;
+        // routed while an earlier wait was abandoned, the device may be long gone
+        while let Some(Some(_)) = detections.recv().now_or_never() {}
+
+        let _waiting = Waiting::new(&self.waiting);
+        let detection = detections
+            .recv()
+            .await
+            .ok_or_else(|| DoorDetectorError::Closed(self.door.clone()))?
+            .map_err(DoorDetectorError::Detector)?;
+        *self.last.lock().unwrap() = Some((detection.source, detection.departure));
+        Ok(detection.device)
+    }
+
+    fn source(&self, _device: &Self::Device) -> String {
+        self.last
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(source, _)| source.clone())
+            .unwrap_or_default()
+    }
+
+    async fn wait_for_departure(&self, device: &Self::Device)
+    where
+        Self::Device: Sync,
+    {
+        let departure = self
+            .last
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(_, departure)| departure.clone());
+        match departure {
+            Some(departure) => departure(device.clone()).await,
+            None => future::pending().await,
+        }
+    }
+
+    async fn restart(&self) -> Result<(), Self::DetectorError> {
+        let restarts = self.restarts.lock().unwrap().clone();
+        let mut result = Ok(());
+        for restart in restarts {
+            if let Err(e) = restart().await {
+                result = Err(DoorDetectorError::Detector(e));
+            }
+        }
+        result
+    }
+}
+
+/// The state of a door a router keeps
+struct Route<Device> {
+    door: String,
+    target: DoorSender<Device>,
+    /// Detections made while the door was busy, handed over once it waits again
+    missed: Vec<Detection<Device>>,
+}
+
+impl<Device: Display + PartialEq> Route<Device> {
+    fn is_waiting(&self) -> bool {
+        *self.target.waiting_changes.borrow()
+    }
+
+    /// Hands a detection or error to the door if it is waiting, `false` if it is not
+    fn send(&self, routed: Routed<Device>) -> bool {
+        if !self.is_waiting() {
+            return false;
+        }
+        match self.target.sender.try_send(routed) {
+            Ok(()) => {
+                // the door stops waiting once it takes the detection, stop polling right away
+                let _ = self.target.waiting.send(false);
+                true
+            }
+            Err(TrySendError::Full(_)) => false,
+            Err(TrySendError::Closed(_)) => {
+                debug!("Door {} stopped", self.door);
+                true
+            }
+        }
+    }
+
+    /// Keeps a detection the door was too busy for, unless it already has one of the device
+    fn miss(&mut self, detection: Detection<Device>) {
+        debug!("Door {} busy, keeping {}", self.door, detection.device);
+        if !self
+            .missed
+            .iter()
+            .any(|missed| missed.device == detection.device)
+        {
+            self.missed.push(detection);
+        }
+    }
+
+    /// Hands the oldest missed detection of a device still in range to the door if it waits
+    fn catch_up(&mut self)
+    where
+        Device: Clone,
+    {
+        while self.is_waiting() && !self.missed.is_empty() {
+            let detection = self.missed.remove(0);
+            if (detection.departure)(detection.device.clone())
+                .now_or_never()
+                .is_some()
+            {
+                debug!(
+                    "Dropping missed detection of departed device {}",
+                    detection.device
+                );
+                continue;
+            }
+            let device = detection.device.clone();
+            if self.send(Ok(detection)) {
+                debug!("Routed missed device {} to door {}", device, self.door);
+            }
+        }
+    }
+}
+
+/// Resolves once any of the doors started or stopped waiting
+async fn waiting_changed<Device>(routes: &mut [Route<Device>]) {
+    let changes = routes.iter_mut().map(|route| {
+        let waiting = &mut route.target.waiting_changes;
+        async move {
+            // a door that stopped does not wait again
+            if waiting.changed().await.is_err() {
+                future::pending::<()>().await;
+            }
+        }
+        .boxed()
+    });
+    let changes = changes.collect::<Vec<_>>();
+    if changes.is_empty() {
+        return future::pending().await;
+    }
+    future::select_all(changes).await;
+}
+
+type Permit<Device> = Arc<dyn Fn(&Device, &str) -> bool + Send + Sync>;
+type Door = JoinHandle<Result<(), Box<dyn ServiceError>>>;
+
+/// Runs several doors sharing detectors
+///
+/// Every door is driven by its own [`Manager`] which gets its detections from a [`DoorDetector`].
+/// Detectors are shared: a detection is routed to all doors the detector serves
+/// and the device is permitted for, doors that are busy get it once they wait again.
+/// Authenticators keep track of their pending request, every door needs its own instance,
+/// e.g. several authenticators using the same client.
+pub struct Supervisor<Device> {
+    doors: HashMap<String, DoorSender<Device>>,
+    permit: Permit<Device>,
+    shutdown: CancellationToken,
+    /// Stops the routers once all doors stopped, doors must not see their detections end first
+    stop_routers: CancellationToken,
+    routers: Vec<JoinHandle<()>>,
+    managers: Vec<(String, Door)>,
+}
+
+impl<Device> Supervisor<Device>
+where
+    Device: Debug + Display + Hash + Eq + Clone + Restricted + Send + Sync + 'static,
+{
+    /// The supervisor and all doors stop once `shutdown` is cancelled
+    pub fn new(shutdown: CancellationToken) -> Self {
+        Self {
+            doors: HashMap::new(),
+            permit: Arc::new(|_, _| true),
+            shutdown,
+            stop_routers: CancellationToken::new(),
+            routers: Vec::new(),
+            managers: Vec::new(),
+        }
+    }
+
+    /// Restrict which doors a device may open, all devices are permitted for all doors by default
+    pub fn permit(&mut self, permit: impl Fn(&Device, &str) -> bool + Send + Sync + 'static) {
+        self.permit = Arc::new(permit);
+    }
+
+    /// Adds a door, the returned detector has to be passed to the door's manager
+    pub fn door(&mut self, door: impl Into<String>) -> DoorDetector<Device> {
+        let door = door.into();
+        let (sender, receiver) = mpsc::channel(1);
+        let (waiting, waiting_changes) = watch::channel(false);
+        let waiting = Arc::new(waiting);
+        let restarts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        self.doors.insert(
+            door.clone(),
+            DoorSender {
+                sender,
+                waiting: waiting.clone(),
+                waiting_changes,
+                restarts: restarts.clone(),
+            },
+        );
+
+        DoorDetector {
+            door,
+            detections: Mutex::new(receiver),
+            waiting,
+            restarts,
+            last: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Routes the detections of `detector` to the given doors
+    ///
+    /// The detector is only polled while one of the doors waits for a device,
+    /// services sharing its input, e.g. stdin, are not cut off while the doors are busy.
+    /// Detections a busy door could not take are handed to it once it waits again,
+    /// unless the device departed in the meantime.
+    /// Errors are passed on to the waiting doors, which may restart the detector.
+    pub fn detector<Detect>(
+        &mut self,
+        detector: Detect,
+        doors: &[&str],
+    ) -> Result<(), SupervisorError>
+    where
+        Detect: Detector<Device = Device> + Send + Sync + 'static,
+        Detect::DetectorError: 'static,
+    {
+        let mut routes = doors
+            .iter()
+            .map(|door| {
+                self.doors
+                    .get(*door)
+                    .map(|target| Route {
+                        door: door.to_string(),
+                        target: target.clone(),
+                        missed: Vec::new(),
+                    })
+                    .ok_or_else(|| SupervisorError::UnknownDoor(door.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let permit = self.permit.clone();
+        let stop = self.stop_routers.clone();
+
+        let detector = Arc::new(detector);
+        let departure: Departure<Device> = {
+            let detector = detector.clone();
+            Arc::new(move |device| {
+                let detector = detector.clone();
+                async move { detector.wait_for_departure(&device).await }.boxed()
+            })
+        };
+        let failed = Arc::new(AtomicBool::new(false));
+        let restart: Restart = {
+            let detector = detector.clone();
+            let failed = failed.clone();
+            Arc::new(move || {
+                let detector = detector.clone();
+                // doors sharing the detector all restart it after it failed, only the first does
+                let failed = failed.swap(false, Ordering::SeqCst);
+                async move {
+                    if !failed {
+                        return Ok(());
+                    }
+                    info!("Restarting detector");
+                    detector
+                        .restart()
+                        .await
+                        .map_err(|e| Arc::new(e) as Arc<dyn ServiceError>)
+                }
+                .boxed()
+            })
+        };
+        for route in routes.iter() {
+            route.target.restarts.lock().unwrap().push(restart.clone());
+        }
+
+        let router = async move {
+            loop {
+                // changes up to here are taken into account below
+                for route in routes.iter_mut() {
+                    let _ = route.target.waiting_changes.changed().now_or_never();
+                    route.catch_up();
+                }
+
+                if !routes.iter().any(Route::is_waiting) {
+                    tokio::select! {
+                        _ = waiting_changed(&mut routes) => continue,
+                        _ = stop.cancelled() => break,
+                    }
+                }
+
+                // reading is given up as soon as a door starts or stops waiting
+                let device = tokio::select! {
+                    device = detector.wait_for_device() => device,
+                    _ = waiting_changed(&mut routes) => continue,
+                    _ = stop.cancelled() => break,
+                };
+                let device = match device {
+                    Ok(device) => device,
+                    Err(e) => {
+                        warn!("Detector failed: {}", e);
+                        failed.store(true, Ordering::SeqCst);
+                        let error = Arc::new(e) as Arc<dyn ServiceError>;
+                        let mut reported = false;
+                        for route in routes.iter() {
+                            reported |= route.send(Err(error.clone()));
+                        }
+                        if !reported {
+                            error!("No door waiting to handle detector error: {}", error);
+                        }
+                        continue;
+                    }
+                };
+
+                let source = detector.source(&device);
+                for route in routes.iter_mut() {
+                    if !permit(&device, &route.door) {
+                        debug!("Device {} not permitted for door {}", device, route.door);
+                        continue;
+                    }
+                    let detection = Detection {
+                        device: device.clone(),
+                        source: source.clone(),
+                        departure: departure.clone(),
+                    };
+                    if route.send(Ok(detection.clone())) {
+                        debug!("Routed device {} to door {}", device, route.door);
+                    } else {
+                        route.miss(detection);
+                    }
+                }
+            }
+        };
+
+        self.routers.push(tokio::spawn(router));
+        Ok(())
+    }
+
+    /// Runs the manager of a door
+    pub fn manage<Auth, Act, Lock>(
+        &mut self,
+        door: impl Into<String>,
+        mut manager: Manager<DoorDetector<Device>, Auth, Act, Lock>,
+    ) where
+        Auth: Authenticate<Device = Device> + Send + Sync + 'static,
+        Act: Actuator + Send + Sync + 'static,
+        Lock: Locker + Send + Sync + 'static,
+        Auth::AuthenticateError: 'static,
+        Act::ActuatorError: 'static,
+        Lock::LockerError: 'static,
+    {
+        let shutdown = self.shutdown.clone();
+        let daemon = async move {
+            manager
+                .daemon(shutdown)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn ServiceError>)
+        };
+
+        self.managers.push((door.into(), tokio::spawn(daemon)));
+    }
+
+    /// Waits for all doors to stop
+    ///
+    /// A failing door does not stop the others, the first error is returned once all stopped.
+    pub async fn run(self) -> Result<(), SupervisorError> {
+        // channels close once all routers feeding them stopped
+        drop(self.doors);
+
+        let mut result = Ok(());
+        for (door, manager) in self.managers {
+            match manager.await? {
+                Ok(()) => info!("Door {} stopped", door),
+                Err(error) => {
+                    error!("Door {} failed: {}", door, error);
+                    if result.is_ok() {
+                        result = Err(SupervisorError::Door { door, error });
+                    }
+                }
+            }
+        }
+
+        // doors only stop on shutdown or failure, stop the routers in either case
+        self.stop_routers.cancel();
+        for router in self.routers {
+            router.await?;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::{sleep, Instant};
+    use tokio_util::sync::CancellationToken;
+
+    use super::Supervisor;
+    use crate::{
+        interfaces::services::{AuthenticateResult, Withdrawal},
+        manager::{Config, Manager, Recovery, RecoveryConfig, RetryPolicy},
+        testing::{
+            secs, AuthenticatorCall, DetectorCall, MockActuator, MockAuthenticator, MockDetector,
+            MockDevice, MockError, MockLocker,
+        },
+    };
+
+    /// Runs a single door fed by `detector` until `duration` passed
+    async fn run_door(
+        detector: MockDetector<MockDevice>,
+        auth: MockAuthenticator<MockDevice>,
+        act: MockActuator,
+        locker: MockLocker,
+        config: Config,
+        duration: Duration,
+    ) {
+        let shutdown = CancellationToken::new();
+        let mut supervisor = Supervisor::new(shutdown.clone());
+        let door = supervisor.door("front");
+        supervisor.detector(detector, &["front"]).unwrap();
+        supervisor.manage("front", Manager::new(door, auth, act, locker, config));
+
+        tokio::spawn(async move {
+            sleep(duration).await;
+            shutdown.cancel();
+        });
+        supervisor.run().await.unwrap();
+    }
+
+    /// The devices the authenticator was asked for and when, relative to `start`
+    fn authenticated(
+        auth: &MockAuthenticator<MockDevice>,
+        start: Instant,
+    ) -> Vec<(Duration, String)> {
+        auth.calls()
+            .into_iter()
+            .filter_map(|(at, call)| match call {
+                AuthenticatorCall::Authenticate(device, _) => {
+                    Some((at - start, device.to_string()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn detector_is_not_polled_while_the_door_is_busy() {
+        let start = Instant::now();
+        let detector = MockDetector::new()
+            .detect(secs(1), MockDevice::new("phone"))
+            .detect(secs(5), MockDevice::new("tablet"));
+        let auth = MockAuthenticator::new()
+            .answer(secs(1), AuthenticateResult::Allow)
+            .answer(secs(1), AuthenticateResult::Allow);
+        let locker = MockLocker::new().lock(secs(0)).lock(secs(20));
+
+        run_door(
+            detector,
+            auth.clone(),
+            MockActuator::new(),
+            locker,
+            Config::default(),
+            secs(100),
+        )
+        .await;
+
+        // the detector is asked for the tablet once the door was relocked at 22s
+        assert_eq!(
+            authenticated(&auth, start),
+            vec![
+                (secs(1), "phone".to_string()),
+                (secs(27), "tablet".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn busy_door_gets_missed_detections_once_it_waits() {
+        let start = Instant::now();
+        let detector = MockDetector::new()
+            .detect(secs(1), MockDevice::new("phone"))
+            .detect(secs(1), MockDevice::new("tablet"));
+        let front = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Deny);
+        let back = MockAuthenticator::new().answer(secs(10), AuthenticateResult::Deny);
+
+        let shutdown = CancellationToken::new();
+        let mut supervisor = Supervisor::new(shutdown.clone());
+        let front_door = supervisor.door("front");
+        let back_door = supervisor.door("back");
+        supervisor.detector(detector, &["front", "back"]).unwrap();
+        let door = |detector, auth| {
+            let locker = MockLocker::new().lock(secs(0));
+            Manager::new(
+                detector,
+                auth,
+                MockActuator::new(),
+                locker,
+                Config::default(),
+            )
+        };
+        supervisor.manage("front", door(front_door, front.clone()));
+        supervisor.manage("back", door(back_door, back.clone()));
+        tokio::spawn(async move {
+            sleep(secs(100)).await;
+            shutdown.cancel();
+        });
+        supervisor.run().await.unwrap();
+
+        // the tablet was detected at 3s while the back door was still asking about the phone
+        assert_eq!(
+            authenticated(&front, start),
+            vec![
+                (secs(1), "phone".to_string()),
+                (secs(3), "tablet".to_string())
+            ]
+        );
+        assert_eq!(
+            authenticated(&back, start),
+            vec![
+                (secs(1), "phone".to_string()),
+                (secs(11), "tablet".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_detector_is_restarted_through_the_door() {
+        let start = Instant::now();
+        let detector = MockDetector::new()
+            .fail(secs(1), MockError::new("adapter reset"))
+            .detect(secs(1), MockDevice::new("phone"));
+        let auth = MockAuthenticator::new().answer(secs(1), AuthenticateResult::Deny);
+        let locker = MockLocker::new().lock(secs(0));
+        let config = Config {
+            recovery: RecoveryConfig::all(Recovery::Restart(RetryPolicy {
+                attempts: Some(3),
+                delay: secs(1),
+                max_delay: secs(10),
+            })),
+            ..Config::default()
+        };
+
+        run_door(
+            detector.clone(),
+            auth.clone(),
+            MockActuator::new(),
+            locker,
+            config,
+            secs(100),
+        )
+        .await;
+
+        assert!(detector
+            .calls()
+            .iter()
+            .any(|(_, call)| *call == DetectorCall::Restart));
+        // failed at 1s, retried after 1s
+        assert_eq!(
+            authenticated(&auth, start),
+            vec![(secs(3), "phone".to_string())]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn departures_reach_the_door() {
+        let start = Instant::now();
+        let detector = MockDetector::new()
+            .detect(secs(1), MockDevice::new("phone"))
+            .depart(secs(5));
+        let auth = MockAuthenticator::new().answer(secs(10), AuthenticateResult::Allow);
+        let act = MockActuator::new();
+        let locker = MockLocker::new().lock(secs(0));
+
+        run_door(
+            detector,
+            auth.clone(),
+            act.clone(),
+            locker,
+            Config::default(),
+            secs(100),
+        )
+        .await;
+
+        let withdrawn = auth
+            .calls()
+            .into_iter()
+            .filter(|(_, call)| *call == AuthenticatorCall::Withdraw(Withdrawal::Left))
+            .map(|(at, _)| at - start)
+            .collect::<Vec<_>>();
+        assert_eq!(withdrawn, vec![secs(6)]);
+        assert_eq!(act.opened(), 0);
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::{Calls, MockError, Script};
+use crate::interfaces::services::Actuator;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActuatorCall {
+    Open,
+    Restart,
+}
+
+/// Opens with the scripted results, succeeds once the script is exhausted
+#[derive(Clone)]
+pub struct MockActuator {
+    script: Script<()>,
+    calls: Calls<ActuatorCall>,
+}
+
+impl Default for MockActuator {
+    fn default() -> Self {
+        Self {
+            script: Script::new(),
+            calls: Calls::new(),
+        }
+    }
+}
+
+impl MockActuator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail the next unscripted opening
+    pub fn fail(self, error: MockError) -> Self {
+        self.script.push(Duration::ZERO, Err(error));
+        self
+    }
+
+    /// Succeed at the next unscripted opening
+    pub fn succeed(self) -> Self {
+        self.script.push(Duration::ZERO, Ok(()));
+        self
+    }
+
+    /// Number of attempts to open the door
+    pub fn opened(&self) -> usize {
+        self.calls()
+            .iter()
+            .filter(|(_, call)| *call == ActuatorCall::Open)
+            .count()
+    }
+
+    pub fn calls(&self) -> Vec<(Instant, ActuatorCall)> {
+        self.calls.get()
+    }
+}
+
+impl Actuator for MockActuator {
+    type ActuatorError = MockError;
+
+    fn open(&mut self) -> Result<(), Self::ActuatorError> {
+        self.calls.record(ActuatorCall::Open);
+        self.script
+            .pop()
+            .map(|(_, result)| result)
+            .unwrap_or(Ok(()))
+    }
+
+    fn restart(&mut self) -> Result<(), Self::ActuatorError> {
+        self.calls.record(ActuatorCall::Restart);
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt::Debug, time::Duration};
+
+use async_trait::async_trait;
+use tokio::time::{timeout as within, Instant};
+
+use super::{Calls, MockError, Script};
+use crate::interfaces::services::{Authenticate, AuthenticateResult, Notice, Withdrawal};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthenticatorCall<D> {
+    Authenticate(D, Option<Duration>),
+    Withdraw(Withdrawal),
+    /// The notice formatted with [`Debug`]
+    Inform(String),
+    WaitForAcknowledgement,
+    Restart,
+}
+
+/// Answers with the scripted results, never answers once the script is exhausted
+///
+/// Answers scripted later than the timeout passed to
+/// [`authenticate`](Authenticate::authenticate) turn into [`AuthenticateResult::Timeout`].
+/// Acknowledgements of alerts are scripted apart from answers.
+pub struct MockAuthenticator<D> {
+    script: Script<AuthenticateResult>,
+    acknowledgements: Script<()>,
+    calls: Calls<AuthenticatorCall<D>>,
+}
+
+impl<D> Clone for MockAuthenticator<D> {
+    fn clone(&self) -> Self {
+        Self {
+            script: self.script.clone(),
+            acknowledgements: self.acknowledgements.clone(),
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<D: Clone> Default for MockAuthenticator<D> {
+    fn default() -> Self {
+        Self {
+            script: Script::new(),
+            acknowledgements: Script::new(),
+            calls: Calls::new(),
+        }
+    }
+}
+
+impl<D: Clone> MockAuthenticator<D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `after` the request was made
+    pub fn answer(self, after: Duration, result: AuthenticateResult) -> Self {
+        self.script.push(after, Ok(result));
+        self
+    }
+
+    /// Fail `after` the request was made
+    pub fn fail(self, after: Duration, error: MockError) -> Self {
+        self.script.push(after, Err(error));
+        self
+    }
+
+    /// Acknowledge an alert `after` the authenticator was asked to wait for it
+    pub fn acknowledge(self, after: Duration) -> Self {
+        self.acknowledgements.push(after, Ok(()));
+        self
+    }
+
+    pub fn calls(&self) -> Vec<(Instant, AuthenticatorCall<D>)> {
+        self.calls.get()
+    }
+}
+
+#[async_trait]
+impl<D: Debug + Clone + Send + Sync> Authenticate for MockAuthenticator<D> {
+    type Device = D;
+    type AuthenticateError = MockError;
+
+    async fn authenticate(
+        &self,
+        device: &Self::Device,
+        timeout: Option<Duration>,
+    ) -> Result<AuthenticateResult, Self::AuthenticateError> {
+        self.calls
+            .record(AuthenticatorCall::Authenticate(device.clone(), timeout));
+
+        match timeout {
+            Some(timeout) => within(timeout, self.script.next())
+                .await
+                .unwrap_or(Ok(AuthenticateResult::Timeout)),
+            None => self.script.next().await,
+        }
+    }
+
+    async fn withdraw(&self, reason: Withdrawal) -> Result<(), Self::AuthenticateError> {
+        self.calls.record(AuthenticatorCall::Withdraw(reason));
+        Ok(())
+    }
+
+    async fn inform(
+        &self,
+        notice: &Notice<'_, Self::Device>,
+    ) -> Result<(), Self::AuthenticateError> {
+        self.calls
+            .record(AuthenticatorCall::Inform(format!("{:?}", notice)));
+        Ok(())
+    }
+
+    async fn acknowledged(&self) -> Result<(), Self::AuthenticateError> {
+        self.calls.record(AuthenticatorCall::WaitForAcknowledgement);
+        self.acknowledgements.next().await
+    }
+
+    fn approver(&self) -> Option<String> {
+        Some("mock".to_string())
+    }
+
+    async fn restart(&self) -> Result<(), Self::AuthenticateError> {
+        self.calls.record(AuthenticatorCall::Restart);
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::{fmt::Debug, time::Duration};
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use super::{Calls, MockError, Script};
+use crate::interfaces::services::Detector;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DetectorCall {
+    WaitForDevice,
+    WaitForDeparture,
+    Restart,
+}
+
+/// Detects the scripted devices, waits forever once the script is exhausted
+///
+/// Departures are scripted apart from detections and apply to whatever device is asked for.
+pub struct MockDetector<D> {
+    script: Script<D>,
+    departures: Script<()>,
+    calls: Calls<DetectorCall>,
+}
+
+impl<D> Clone for MockDetector<D> {
+    fn clone(&self) -> Self {
+        Self {
+            script: self.script.clone(),
+            departures: self.departures.clone(),
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<D> Default for MockDetector<D> {
+    fn default() -> Self {
+        Self {
+            script: Script::new(),
+            departures: Script::new(),
+            calls: Calls::new(),
+        }
+    }
+}
+
+impl<D> MockDetector<D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Detect `device` `after` the detector was asked
+    pub fn detect(self, after: Duration, device: D) -> Self {
+        self.script.push(after, Ok(device));
+        self
+    }
+
+    /// Fail `after` the detector was asked
+    pub fn fail(self, after: Duration, error: MockError) -> Self {
+        self.script.push(after, Err(error));
+        self
+    }
+
+    /// Report a departure `after` the detector was asked to wait for one
+    pub fn depart(self, after: Duration) -> Self {
+        self.departures.push(after, Ok(()));
+        self
+    }
+
+    pub fn calls(&self) -> Vec<(Instant, DetectorCall)> {
+        self.calls.get()
+    }
+}
+
+#[async_trait]
+impl<D: Debug + Send> Detector for MockDetector<D> {
+    type Device = D;
+    type DetectorError = MockError;
+
+    async fn wait_for_device(&self) -> Result<Self::Device, Self::DetectorError> {
+        self.calls.record(DetectorCall::WaitForDevice);
+        self.script.next().await
+    }
+
+    fn source(&self, _device: &Self::Device) -> String {
+        "mock".to_string()
+    }
+
+    async fn wait_for_departure(&self, _device: &Self::Device)
+    where
+        Self::Device: Sync,
+    {
+        self.calls.record(DetectorCall::WaitForDeparture);
+        let _ = self.departures.next().await;
+    }
+
+    async fn restart(&self) -> Result<(), Self::DetectorError> {
+        self.calls.record(DetectorCall::Restart);
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt::{self, Display};
+
+use crate::access::{Access, Restricted};
+
+/// A device identified by its name, with configurable [`Access`] rules
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MockDevice {
+    pub name: String,
+    pub access: Access,
+}
+
+impl MockDevice {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            access: Access::default(),
+        }
+    }
+
+    pub fn with_access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+}
+
+impl Display for MockDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Restricted for MockDevice {
+    fn access(&self) -> &Access {
+        &self.access
+    }
+}
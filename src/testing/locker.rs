@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use super::{Calls, MockError, Script};
+use crate::interfaces::services::{HoldOpen, Locker};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockerCall {
+    WaitForLock,
+    ConfirmLock,
+    HoldOpen,
+    Restart,
+}
+
+/// Requests locks and hold open changes as scripted, never requests once a script is exhausted
+#[derive(Clone)]
+pub struct MockLocker {
+    locks: Script<()>,
+    hold_open: Script<HoldOpen>,
+    calls: Calls<LockerCall>,
+}
+
+impl Default for MockLocker {
+    fn default() -> Self {
+        Self {
+            locks: Script::new(),
+            hold_open: Script::new(),
+            calls: Calls::new(),
+        }
+    }
+}
+
+impl MockLocker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the lock `after` the locker was asked
+    pub fn lock(self, after: Duration) -> Self {
+        self.locks.push(after, Ok(()));
+        self
+    }
+
+    /// Fail `after` the locker was asked to wait for the lock
+    pub fn fail(self, after: Duration, error: MockError) -> Self {
+        self.locks.push(after, Err(error));
+        self
+    }
+
+    /// Extend or cancel the relock timer `after` the locker was asked
+    pub fn hold_open(self, after: Duration, request: HoldOpen) -> Self {
+        self.hold_open.push(after, Ok(request));
+        self
+    }
+
+    pub fn calls(&self) -> Vec<(Instant, LockerCall)> {
+        self.calls.get()
+    }
+}
+
+#[async_trait]
+impl Locker for MockLocker {
+    type LockerError = MockError;
+
+    async fn wait_for_lock(&self) -> Result<(), Self::LockerError> {
+        self.calls.record(LockerCall::WaitForLock);
+        self.locks.next().await
+    }
+
+    async fn confirm_lock(&self) -> Result<(), Self::LockerError> {
+        self.calls.record(LockerCall::ConfirmLock);
+        Ok(())
+    }
+
+    async fn hold_open(&self) -> Result<HoldOpen, Self::LockerError> {
+        self.calls.record(LockerCall::HoldOpen);
+        self.hold_open.next().await
+    }
+
+    async fn restart(&self) -> Result<(), Self::LockerError> {
+        self.calls.record(LockerCall::Restart);
+        Ok(())
+    }
+}
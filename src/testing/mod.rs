@@ -0,0 +1,146 @@
+//! Scripted mock services for tests
+//!
+//! Every mock replays a script of results, each after an optional delay,
+//! and records its calls with the (tokio) time they were made.
+//! Mocks are cheap handles: keep a clone to inspect the calls after moving one into a
+//! [`Manager`](crate::manager::Manager).
+//! With paused tokio time (`#[tokio::test(start_paused = true)]`) delays and timeouts
+//! of the manager are deterministic.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use thiserror::Error;
+use tokio::time::{sleep, Instant};
+
+use crate::interfaces::services::ServiceError;
+
+mod actuator;
+mod authenticator;
+mod detector;
+mod device;
+mod locker;
+
+pub use actuator::{ActuatorCall, MockActuator};
+pub use authenticator::{AuthenticatorCall, MockAuthenticator};
+pub use detector::{DetectorCall, MockDetector};
+pub use device::MockDevice;
+pub use locker::{LockerCall, MockLocker};
+
+/// Shorthand for [`Duration::from_secs`] in scripts
+pub const fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+/// Error returned by a mock as scripted
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message}")]
+pub struct MockError {
+    pub message: String,
+    pub fatal: bool,
+}
+
+impl MockError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            fatal: false,
+        }
+    }
+
+    pub fn fatal(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            fatal: true,
+        }
+    }
+}
+
+impl ServiceError for MockError {
+    fn is_fatal(&self) -> bool {
+        self.fatal
+    }
+}
+
+/// A result and the delay before it is returned
+type Step<T> = (Duration, Result<T, MockError>);
+
+/// Results replayed in order, each after its delay
+struct Script<T> {
+    steps: Arc<Mutex<VecDeque<Step<T>>>>,
+}
+
+impl<T> Clone for Script<T> {
+    fn clone(&self) -> Self {
+        Self {
+            steps: self.steps.clone(),
+        }
+    }
+}
+
+impl<T> Script<T> {
+    fn new() -> Self {
+        Self {
+            steps: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    fn push(&self, after: Duration, result: Result<T, MockError>) {
+        self.steps.lock().unwrap().push_back((after, result));
+    }
+
+    /// The next step without waiting for its delay
+    fn pop(&self) -> Option<Step<T>> {
+        self.steps.lock().unwrap().pop_front()
+    }
+
+    /// Waits for the delay of the next step and returns its result,
+    /// never returns once the script is exhausted
+    ///
+    /// The step is only taken once its delay passed, a call dropped before
+    /// (e.g. by a `select!`) leaves it to the next call, which waits for the full delay again.
+    async fn next(&self) -> Result<T, MockError> {
+        let after = self.steps.lock().unwrap().front().map(|(after, _)| *after);
+        match after {
+            Some(after) => sleep(after).await,
+            None => futures::future::pending().await,
+        }
+
+        match self.pop() {
+            Some((_, result)) => result,
+            None => futures::future::pending().await,
+        }
+    }
+}
+
+/// Calls made to a mock, with the time they were made
+struct Calls<C> {
+    calls: Arc<Mutex<Vec<(Instant, C)>>>,
+}
+
+impl<C> Clone for Calls<C> {
+    fn clone(&self) -> Self {
+        Self {
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<C: Clone> Calls<C> {
+    fn new() -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn record(&self, call: C) {
+        self.calls.lock().unwrap().push((Instant::now(), call));
+    }
+
+    fn get(&self) -> Vec<(Instant, C)> {
+        self.calls.lock().unwrap().clone()
+    }
+}